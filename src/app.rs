@@ -1,4 +1,4 @@
-use std::{error::Error, fmt, io::Error as IoError, sync::Arc};
+use std::{error::Error, fmt, io::Error as IoError, sync::Arc, time::Duration};
 
 use carapax::{
     App, Context,
@@ -6,20 +6,33 @@ use carapax::{
     api::{Client, ClientError},
     handler::{LongPoll, WebhookServer},
     session::SessionManager,
+    types::ChatPeerId,
 };
 use clap::{Parser, Subcommand};
-use redis::RedisError;
-use refinery::Error as MigrationError;
+use deadpool_postgres::{Pool as PgPool, PoolError as PgPoolError};
+use time::macros::format_description;
 use tokio::spawn;
-use tokio_postgres::{Client as PgClient, Error as PgError, NoTls as PgNoTls, connect as pg_connect};
+use tokio_postgres::Client as PgClient;
 
 use crate::{
     config::{Config, ConfigError},
-    handlers, migrations,
-    services::NotesService,
-    session::create_session_backend,
+    crypto::{Cipher, CipherError},
+    database::{self, DatabaseError},
+    handlers::{self, AddState},
+    migrations::{self, MigrationError},
+    services::{
+        ArchiveError, ArchiveService, DeliveryService, DialogueCollector, DigestScheduler, DigestsService,
+        NotesCollector, NotesService, NotesServiceError, ReminderScheduler, RemindersService,
+    },
+    session::{SessionBackendError, create_session_backend},
 };
 
+const ARCHIVE_PERIOD: Duration = Duration::from_secs(3600);
+const NOTES_GC_PERIOD: Duration = Duration::from_secs(600);
+const DIALOGUES_PERIOD: Duration = Duration::from_secs(60);
+const DIGESTS_PERIOD: Duration = Duration::from_secs(60);
+const REMINDERS_PERIOD: Duration = Duration::from_secs(30);
+
 #[derive(Parser)]
 #[clap(about, author, version)]
 pub struct Arguments {
@@ -32,57 +45,244 @@ pub struct Arguments {
 
 #[derive(Subcommand)]
 pub enum Command {
+    /// Download files of media notes into the archive
+    Archive,
     /// Run migrations
-    Migrate,
+    #[clap(args_conflicts_with_subcommands = true)]
+    Migrate {
+        /// Print SQL instead of executing it
+        #[clap(long)]
+        dry_run: bool,
+        #[clap(subcommand)]
+        command: Option<MigrateCommand>,
+    },
+    /// Upload archived files using the current token
+    Restore {
+        /// Chat to upload files to, uploaded messages are deleted right after
+        chat_id: i64,
+    },
+    /// Encrypt all notes using the first configured key
+    RotateKeys,
+    /// Rewrite notes stored in an outdated format
+    UpgradeNotes,
     /// Start bot
     Start,
 }
 
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// List applied and pending migrations
+    Status,
+    /// Revert the latest applied migrations
+    Rollback {
+        /// Number of migrations to revert
+        #[clap(default_value_t = 1)]
+        count: usize,
+        /// Print SQL instead of executing it
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
 pub async fn run() -> Result<(), AppError> {
     let args = Arguments::parse();
 
     let config = Config::read_from_file(args.config).map_err(AppError::ReadConfig)?;
 
-    let (mut pg_client, pg_connection) = pg_connect(&config.database_url, PgNoTls)
-        .await
-        .map_err(AppError::PgConnect)?;
-
-    spawn(async move {
-        if let Err(err) = pg_connection.await {
-            log::error!("PostgreSQL connection error: {}", err);
-        }
-    });
+    let pg_pool = database::create_pool(
+        &config.database_url,
+        &config.database_pool,
+        config.database_tls.as_ref(),
+    )
+    .map_err(AppError::Database)?;
 
     match args.command {
-        Command::Migrate => {
-            migrations::run(&mut pg_client).await.map_err(AppError::Migrate)?;
+        Command::Archive => {
+            let archive_service = create_archive_service(config, pg_pool)?;
+            let count = archive_service.archive_pending().await.map_err(AppError::Archive)?;
+            log::info!("Archived {count} notes");
+        }
+        Command::Restore { chat_id } => {
+            let archive_service = create_archive_service(config, pg_pool)?;
+            let count = archive_service
+                .restore(ChatPeerId::from(chat_id))
+                .await
+                .map_err(AppError::Archive)?;
+            log::info!("Restored {count} notes");
+        }
+        Command::Migrate { dry_run, command } => {
+            let mut pg_client = pg_pool.get().await.map_err(AppError::PgConnect)?;
+            migrate(&mut pg_client, dry_run, command)
+                .await
+                .map_err(AppError::Migrate)?;
+        }
+        Command::RotateKeys => {
+            let cipher = create_cipher(&config)?.ok_or(AppError::NoEncryption)?;
+            let notes_service = NotesService::new(pg_pool, Some(cipher));
+            let count = notes_service.reencrypt().await.map_err(AppError::RotateKeys)?;
+            log::info!("Encrypted {count} notes");
+        }
+        Command::UpgradeNotes => {
+            let notes_service = NotesService::new(pg_pool, create_cipher(&config)?);
+            let count = notes_service.upgrade().await.map_err(AppError::UpgradeNotes)?;
+            log::info!("Upgraded {count} notes");
         }
         Command::Start => {
-            start(config, pg_client).await?;
+            let mut pg_client = pg_pool.get().await.map_err(AppError::PgConnect)?;
+            check_schema(&mut pg_client, config.auto_migrate).await?;
+            drop(pg_client);
+            start(config, pg_pool).await?;
         }
     }
 
     Ok(())
 }
 
-async fn start(config: Config, pg_client: PgClient) -> Result<(), AppError> {
-    let pg_client = Arc::new(pg_client);
+async fn migrate(
+    pg_client: &mut PgClient,
+    dry_run: bool,
+    command: Option<MigrateCommand>,
+) -> Result<(), MigrationError> {
+    match command {
+        None if dry_run => {
+            for item in migrations::pending_sql(pg_client).await? {
+                println!("-- {} {}\n{}\n", item.number, item.name, item.sql);
+            }
+        }
+        None => {
+            let report = migrations::run(pg_client).await?;
+            log::info!("Applied {} migrations", report.applied_migrations().len());
+        }
+        Some(MigrateCommand::Status) => {
+            let date_format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+            for item in migrations::status(pg_client).await? {
+                let state = match item.applied_on.and_then(|value| value.format(date_format).ok()) {
+                    Some(applied_on) => format!("applied {applied_on}"),
+                    None => String::from("pending"),
+                };
+                println!("{:>3} {:<40} {}", item.number, item.name, state);
+            }
+        }
+        Some(MigrateCommand::Rollback { count, dry_run: true }) => {
+            for item in migrations::rollback_sql(pg_client, count).await? {
+                println!("-- {} {}\n{}\n", item.number, item.name, item.sql);
+            }
+        }
+        Some(MigrateCommand::Rollback { count, dry_run: false }) => {
+            for item in migrations::rollback(pg_client, count).await? {
+                log::info!("Reverted {} {}", item.number, item.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ensures that the database schema matches this build before the bot starts
+async fn check_schema(pg_client: &mut PgClient, auto_migrate: bool) -> Result<(), AppError> {
+    let pending = migrations::pending(pg_client).await.map_err(AppError::Migrate)?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    if !auto_migrate {
+        return Err(AppError::PendingMigrations(
+            pending.into_iter().map(|version| version.name).collect(),
+        ));
+    }
+    let report = migrations::run(pg_client).await.map_err(AppError::Migrate)?;
+    log::info!("Applied {} migrations", report.applied_migrations().len());
+    Ok(())
+}
+
+fn create_cipher(config: &Config) -> Result<Option<Arc<Cipher>>, AppError> {
+    config
+        .encryption
+        .as_ref()
+        .map(|encryption_config| Cipher::new(encryption_config).map(Arc::new))
+        .transpose()
+        .map_err(AppError::Cipher)
+}
+
+fn create_archive_service(config: Config, pg_pool: PgPool) -> Result<ArchiveService, AppError> {
+    let cipher = create_cipher(&config)?;
+    let archive_config = config.archive.ok_or(AppError::NoArchive)?;
+    let client = Client::new(&config.token).map_err(AppError::CreateApiClient)?;
+    let notes_service = NotesService::new(pg_pool, cipher);
+    Ok(ArchiveService::new(
+        client.clone(),
+        DeliveryService::new(client),
+        notes_service,
+        archive_config,
+    ))
+}
+
+async fn start(config: Config, pg_pool: PgPool) -> Result<(), AppError> {
+    let cipher = create_cipher(&config)?;
+    let session_config = config.session_config();
 
     let access_rules: Vec<_> = config.users.into_iter().map(AccessRule::allow_user).collect();
     let admin_policy = InMemoryAccessPolicy::from(access_rules);
 
     let client = Client::new(&config.token).map_err(AppError::CreateApiClient)?;
 
-    let session_backend = create_session_backend(config.session_url)
+    let session_backend = create_session_backend(session_config, &config.session_options, pg_pool.clone())
         .await
-        .map_err(AppError::Redis)?;
+        .map_err(AppError::Session)?;
+
+    let mut dialogue_collector = DialogueCollector::<AddState>::new(
+        client.clone(),
+        session_backend.clone(),
+        Duration::from_secs(config.session_options.dialogue_timeout),
+        DIALOGUES_PERIOD,
+    );
+    spawn(async move { dialogue_collector.run().await });
 
     let session_manager = SessionManager::new(session_backend);
 
+    let notes_service = NotesService::new(pg_pool.clone(), cipher);
+    let mut notes_collector = NotesCollector::new(notes_service.clone(), NOTES_GC_PERIOD);
+    spawn(async move { notes_collector.run().await });
+
+    let delivery_service = DeliveryService::new(client.clone());
+    let reminders_service = RemindersService::new(pg_pool.clone());
+    let mut reminder_scheduler = ReminderScheduler::new(
+        client.clone(),
+        delivery_service.clone(),
+        notes_service.clone(),
+        reminders_service.clone(),
+        REMINDERS_PERIOD,
+    );
+    spawn(async move { reminder_scheduler.run().await });
+
+    let digests_service = DigestsService::new(pg_pool);
+    let mut digest_scheduler = DigestScheduler::new(
+        client.clone(),
+        delivery_service.clone(),
+        digests_service.clone(),
+        notes_service.clone(),
+        DIGESTS_PERIOD,
+    );
+    spawn(async move { digest_scheduler.run().await });
+
+    if let Some(archive_config) = config.archive {
+        let archive_service = ArchiveService::new(
+            client.clone(),
+            delivery_service.clone(),
+            notes_service.clone(),
+            archive_config,
+        );
+        spawn(async move { archive_service.run(ARCHIVE_PERIOD).await });
+    }
+
     let mut context = Context::default();
     context.insert(client.clone());
+    context.insert(delivery_service);
     context.insert(session_manager);
-    context.insert(NotesService::new(pg_client));
+    context.insert(config.session_options);
+    context.insert(config.message_format);
+    context.insert(config.thumbnails);
+    context.insert(notes_service);
+    context.insert(reminders_service);
+    context.insert(digests_service);
 
     let chain = handlers::setup().with_access_policy(admin_policy);
 
@@ -106,26 +306,47 @@ async fn start(config: Config, pg_client: PgClient) -> Result<(), AppError> {
 
 #[derive(Debug)]
 pub enum AppError {
+    Archive(ArchiveError),
+    Cipher(CipherError),
     CreateApiClient(ClientError),
+    Database(DatabaseError),
     Migrate(MigrationError),
+    NoArchive,
     NoConfig,
-    PgConnect(PgError),
+    NoEncryption,
+    PendingMigrations(Vec<String>),
+    PgConnect(PgPoolError),
     ReadConfig(ConfigError),
-    Redis(RedisError),
+    RotateKeys(NotesServiceError),
+    Session(SessionBackendError),
     StartServer(IoError),
+    UpgradeNotes(NotesServiceError),
 }
 
 impl fmt::Display for AppError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::AppError::*;
         match self {
+            Archive(err) => write!(out, "Archive error: {err}"),
+            Cipher(err) => write!(out, "Encryption error: {err}"),
             CreateApiClient(err) => write!(out, "Could not create API client: {err}"),
+            Database(err) => write!(out, "PostgreSQL: {err}"),
             Migrate(err) => write!(out, "Migration error: {err}"),
+            NoArchive => write!(out, "Archive is not configured"),
             NoConfig => write!(out, "Path to configuration file is not provided"),
+            NoEncryption => write!(out, "Encryption is not configured"),
+            PendingMigrations(names) => write!(
+                out,
+                "Database schema is outdated, pending migrations: {}. \
+                 Run the migrate command or set auto_migrate in config",
+                names.join(", ")
+            ),
             PgConnect(err) => write!(out, "PostgreSQL: {err}"),
             ReadConfig(err) => write!(out, "{err}"),
-            Redis(err) => write!(out, "Redis connection error: {err}"),
+            RotateKeys(err) => write!(out, "Could not encrypt notes: {err}"),
+            Session(err) => write!(out, "Could not create session backend: {err}"),
             StartServer(err) => write!(out, "Could not start server for webhooks: {err}"),
+            UpgradeNotes(err) => write!(out, "Could not upgrade notes: {err}"),
         }
     }
 }
@@ -134,13 +355,21 @@ impl Error for AppError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         use self::AppError::*;
         Some(match self {
+            Archive(err) => err,
+            Cipher(err) => err,
             CreateApiClient(err) => err,
+            Database(err) => err,
             Migrate(err) => err,
+            NoArchive => return None,
             NoConfig => return None,
+            NoEncryption => return None,
+            PendingMigrations(_) => return None,
             PgConnect(err) => err,
             ReadConfig(err) => err,
-            Redis(err) => err,
+            RotateKeys(err) => err,
+            Session(err) => err,
             StartServer(err) => err,
+            UpgradeNotes(err) => err,
         })
     }
 }
@@ -1,16 +1,183 @@
 use carapax::types::UserId;
 use serde::Deserialize;
 use serde_yaml::Error as YamlError;
-use std::{error::Error, fmt, fs::read_to_string, io::Error as IoError, net::SocketAddr, path::Path};
+use std::{
+    error::Error,
+    fmt,
+    fs::read_to_string,
+    io::Error as IoError,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use crate::render::RenderMode;
 
 #[derive(Clone, Deserialize)]
 pub struct Config {
     pub token: String,
     pub database_url: String,
-    pub session_url: String,
+    #[serde(default)]
+    pub database_pool: DatabasePoolConfig,
+    pub database_tls: Option<DatabaseTlsConfig>,
+    /// Where sessions are stored, in-memory when neither this nor `session_url` is set
+    pub session: Option<SessionConfig>,
+    /// Redis URL, same as `session: {redis: {url: ...}}`
+    pub session_url: Option<String>,
+    #[serde(default)]
+    pub session_options: SessionOptions,
     pub users: Vec<UserId>,
     pub webhook_address: Option<SocketAddr>,
     pub webhook_path: Option<String>,
+    #[serde(default)]
+    pub message_format: RenderMode,
+    #[serde(default)]
+    pub thumbnails: Thumbnails,
+    pub archive: Option<ArchiveConfig>,
+    pub encryption: Option<EncryptionConfig>,
+    /// Whether pending migrations are applied on start
+    #[serde(default)]
+    pub auto_migrate: bool,
+}
+
+/// PostgreSQL connection pool
+///
+/// Timeouts are in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabasePoolConfig {
+    /// Maximum number of connections
+    pub size: usize,
+    /// How long to wait for a free connection
+    pub wait_timeout: u64,
+    /// How long to wait for a new connection to be established
+    pub connect_timeout: u64,
+    /// How long to wait for a connection to be checked before it is reused
+    pub recycle_timeout: u64,
+    /// Whether a connection runs a test query before it is reused,
+    /// otherwise only connections closed by the server are dropped
+    pub health_check: bool,
+}
+
+impl Default for DatabasePoolConfig {
+    fn default() -> Self {
+        Self {
+            size: 8,
+            wait_timeout: 30,
+            connect_timeout: 10,
+            recycle_timeout: 5,
+            health_check: true,
+        }
+    }
+}
+
+/// TLS of PostgreSQL connections
+#[derive(Clone, Debug, Deserialize)]
+pub struct DatabaseTlsConfig {
+    #[serde(default)]
+    pub mode: SslMode,
+    /// PEM file with certificates of trusted authorities
+    pub root_cert: Option<PathBuf>,
+    /// PEM file with a client certificate chain, requires `client_key`
+    pub client_cert: Option<PathBuf>,
+    /// PEM file with a private key of the client certificate
+    pub client_key: Option<PathBuf>,
+}
+
+/// Same as `sslmode` of libpq, except `allow` which is not supported
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SslMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyCa,
+    #[default]
+    VerifyFull,
+}
+
+/// Storage of sessions which keep dialogue and search state
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionConfig {
+    /// Sessions are lost on restart
+    Memory,
+    /// Uses the `sessions` and `session_values` tables of the database
+    Postgres,
+    Redis {
+        url: String,
+    },
+}
+
+/// Lifetime of sessions
+///
+/// Periods and timeouts are in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionOptions {
+    /// Prefix of Redis keys, must be unique for every bot sharing a Redis database
+    pub namespace: String,
+    /// How often expired sessions are removed
+    pub gc_period: u64,
+    /// Sessions older than this are removed
+    pub lifetime: u64,
+    /// How long an unfinished dialogue waits for a message before it is cancelled
+    pub dialogue_timeout: u64,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            namespace: String::from("tg-assistant-bot"),
+            gc_period: 3_600,
+            lifetime: 86_400 * 30,
+            dialogue_timeout: 1_800,
+        }
+    }
+}
+
+/// Encryption of note content, see `Cipher`
+#[derive(Clone, Debug, Deserialize)]
+pub struct EncryptionConfig {
+    /// Base64-encoded 256-bit keys, the first one is used to encrypt
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Whether keywords are stored as HMAC values
+    #[serde(default)]
+    pub blind_keywords: bool,
+}
+
+/// Where files of media notes are archived
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveConfig {
+    Local { path: PathBuf },
+    S3(S3Config),
+}
+
+/// An S3-compatible object storage, objects are addressed as `{endpoint}/{bucket}/{key}`
+#[derive(Clone, Debug, Deserialize)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    #[serde(default = "S3Config::default_region")]
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+}
+
+impl S3Config {
+    fn default_region() -> String {
+        String::from("us-east-1")
+    }
+}
+
+/// URLs of thumbnails for inline query results
+///
+/// Media results use thumbnails provided by Telegram, so only text and location notes are configurable.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Thumbnails {
+    pub text: Option<String>,
+    pub location: Option<String>,
 }
 
 impl Config {
@@ -18,6 +185,14 @@ impl Config {
         let data = read_to_string(path).map_err(ConfigError::Read)?;
         serde_yaml::from_str(&data).map_err(ConfigError::Parse)
     }
+
+    pub fn session_config(&self) -> SessionConfig {
+        match (&self.session, &self.session_url) {
+            (Some(session), _) => session.clone(),
+            (None, Some(url)) => SessionConfig::Redis { url: url.clone() },
+            (None, None) => SessionConfig::Memory,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -0,0 +1,299 @@
+use std::{env, error::Error, fmt};
+
+use base64::{DecodeError, Engine, engine::general_purpose::STANDARD as BASE64};
+use hmac::{Hmac, Mac};
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::EncryptionConfig;
+
+/// Environment variable with comma separated keys, used when keys are not set in config
+pub const KEYS_ENV: &str = "ASSISTANT_ENCRYPTION_KEYS";
+
+const KEY_LEN: usize = 32;
+
+/// Envelope encryption of note content
+///
+/// Every value is encrypted using a random data key,
+/// the data key is encrypted using a master key and stored alongside.
+/// The first configured master key is used to encrypt, all of them are used to decrypt,
+/// so keys can be rotated without losing access to existing notes.
+pub struct Cipher {
+    keys: Vec<MasterKey>,
+    blind_keywords: bool,
+    random: SystemRandom,
+}
+
+impl Cipher {
+    pub fn new(config: &EncryptionConfig) -> Result<Self, CipherError> {
+        let values = if config.keys.is_empty() {
+            env::var(KEYS_ENV)
+                .map(|value| value.split(',').map(|x| String::from(x.trim())).collect())
+                .unwrap_or_default()
+        } else {
+            config.keys.clone()
+        };
+        if values.is_empty() {
+            return Err(CipherError::NoKeys);
+        }
+        let keys = values
+            .iter()
+            .map(|value| MasterKey::decode(value))
+            .collect::<Result<Vec<MasterKey>, CipherError>>()?;
+        Ok(Self {
+            keys,
+            blind_keywords: config.blind_keywords,
+            random: SystemRandom::new(),
+        })
+    }
+
+    /// Whether keywords are stored as HMAC values instead of a plain text
+    pub fn blind_keywords(&self) -> bool {
+        self.blind_keywords
+    }
+
+    /// Returns a deterministic keyed hash of a value, so it can be compared without being disclosed
+    ///
+    /// The hash depends on the first key, use `blind_all` to look up values stored before a rotation.
+    pub fn blind(&self, value: &str) -> String {
+        self.keys[0].blind(value)
+    }
+
+    /// Returns values blinded with each of the keys, starting from the first one
+    ///
+    /// Stored values keep a hash of the key they were saved with until `rotate-keys` runs.
+    pub fn blind_all(&self, values: &[String]) -> Vec<Vec<String>> {
+        self.keys
+            .iter()
+            .map(|key| values.iter().map(|value| key.blind(value)).collect())
+            .collect()
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Envelope, CipherError> {
+        let master_key = &self.keys[0];
+        let mut data_key = [0; KEY_LEN];
+        self.random.fill(&mut data_key).map_err(|_| CipherError::Random)?;
+        let (nonce, data) = self.encrypt(&data_key, plaintext)?;
+        let (key_nonce, key) = self.encrypt(&master_key.value, &data_key)?;
+        Ok(Envelope {
+            key_id: master_key.id.clone(),
+            key: BASE64.encode([key_nonce.as_slice(), &key].concat()),
+            nonce: BASE64.encode(nonce),
+            data: BASE64.encode(data),
+        })
+    }
+
+    pub fn open(&self, envelope: &Envelope) -> Result<Vec<u8>, CipherError> {
+        let master_key = self
+            .keys
+            .iter()
+            .find(|key| key.id == envelope.key_id)
+            .ok_or_else(|| CipherError::UnknownKey(envelope.key_id.clone()))?;
+        let key = BASE64.decode(&envelope.key).map_err(CipherError::Decode)?;
+        if key.len() < NONCE_LEN {
+            return Err(CipherError::Decrypt);
+        }
+        let (key_nonce, key) = key.split_at(NONCE_LEN);
+        let data_key = decrypt(&master_key.value, key_nonce, key)?;
+        let nonce = BASE64.decode(&envelope.nonce).map_err(CipherError::Decode)?;
+        let data = BASE64.decode(&envelope.data).map_err(CipherError::Decode)?;
+        decrypt(&data_key, &nonce, &data)
+    }
+
+    fn encrypt(&self, key: &[u8], plaintext: &[u8]) -> Result<([u8; NONCE_LEN], Vec<u8>), CipherError> {
+        let mut nonce = [0; NONCE_LEN];
+        self.random.fill(&mut nonce).map_err(|_| CipherError::Random)?;
+        let mut data = plaintext.to_vec();
+        create_key(key)?
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| CipherError::Encrypt)?;
+        Ok((nonce, data))
+    }
+}
+
+fn decrypt(key: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, CipherError> {
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| CipherError::Decrypt)?;
+    let mut data = ciphertext.to_vec();
+    let len = create_key(key)?
+        .open_in_place(nonce, Aad::empty(), &mut data)
+        .map_err(|_| CipherError::Decrypt)?
+        .len();
+    data.truncate(len);
+    Ok(data)
+}
+
+fn create_key(value: &[u8]) -> Result<LessSafeKey, CipherError> {
+    UnboundKey::new(&AES_256_GCM, value)
+        .map(LessSafeKey::new)
+        .map_err(|_| CipherError::KeyLength)
+}
+
+struct MasterKey {
+    /// A fingerprint of the key, tells which key encrypted a value
+    id: String,
+    value: Vec<u8>,
+    /// A key of the blind index, derived from the master key
+    index_key: Vec<u8>,
+}
+
+impl MasterKey {
+    fn decode(value: &str) -> Result<Self, CipherError> {
+        let value = BASE64.decode(value.trim()).map_err(CipherError::Decode)?;
+        if value.len() != KEY_LEN {
+            return Err(CipherError::KeyLength);
+        }
+        Ok(Self {
+            id: hex(&Sha256::digest(&value)[..8]),
+            index_key: hmac(&value, b"keywords"),
+            value,
+        })
+    }
+
+    fn blind(&self, value: &str) -> String {
+        hex(&hmac(&self.index_key, value.as_bytes()))
+    }
+}
+
+/// An encrypted value
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Envelope {
+    /// Fingerprint of the master key
+    pub key_id: String,
+    /// Data key encrypted using the master key
+    pub key: String,
+    pub nonce: String,
+    pub data: String,
+}
+
+/// HMAC-SHA256 of the data
+pub(crate) fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Lowercase hex encoding
+pub(crate) fn hex(data: &[u8]) -> String {
+    data.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+#[derive(Debug)]
+pub enum CipherError {
+    Decode(DecodeError),
+    Decrypt,
+    Encrypt,
+    KeyLength,
+    NoKeys,
+    Random,
+    UnknownKey(String),
+}
+
+impl fmt::Display for CipherError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::CipherError::*;
+        match self {
+            Decode(err) => write!(out, "can not decode base64: {err}"),
+            Decrypt => write!(out, "can not decrypt value"),
+            Encrypt => write!(out, "can not encrypt value"),
+            KeyLength => write!(out, "key must be {KEY_LEN} bytes long"),
+            NoKeys => write!(
+                out,
+                "encryption keys are not provided, set them in config or {KEYS_ENV}"
+            ),
+            Random => write!(out, "can not generate random bytes"),
+            UnknownKey(id) => write!(out, "value is encrypted using an unknown key {id}"),
+        }
+    }
+}
+
+impl Error for CipherError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::CipherError::*;
+        match self {
+            Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_cipher(keys: &[&[u8; KEY_LEN]]) -> Cipher {
+        Cipher::new(&EncryptionConfig {
+            keys: keys.iter().map(|key| BASE64.encode(key)).collect(),
+            blind_keywords: true,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn seal_open() {
+        let cipher = create_cipher(&[&[1; KEY_LEN]]);
+        let envelope = cipher.seal(b"secret").unwrap();
+        assert_ne!(BASE64.decode(&envelope.data).unwrap(), b"secret");
+        assert_eq!(cipher.open(&envelope).unwrap(), b"secret");
+
+        let mut tampered = envelope.clone();
+        tampered.data = BASE64.encode(b"something else");
+        assert!(matches!(cipher.open(&tampered), Err(CipherError::Decrypt)));
+
+        let other = create_cipher(&[&[2; KEY_LEN]]);
+        assert!(matches!(other.open(&envelope), Err(CipherError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn rotation() {
+        let old = create_cipher(&[&[1; KEY_LEN]]);
+        let envelope = old.seal(b"secret").unwrap();
+        let new = create_cipher(&[&[2; KEY_LEN], &[1; KEY_LEN]]);
+        assert_eq!(new.open(&envelope).unwrap(), b"secret");
+        let envelope = new.seal(b"secret").unwrap();
+        assert_ne!(envelope.key_id, old.seal(b"secret").unwrap().key_id);
+        assert!(matches!(old.open(&envelope), Err(CipherError::UnknownKey(_))));
+    }
+
+    #[test]
+    fn blind() {
+        let cipher = create_cipher(&[&[1; KEY_LEN]]);
+        assert_eq!(cipher.blind("keyword"), cipher.blind("keyword"));
+        assert_ne!(cipher.blind("keyword"), cipher.blind("other"));
+        assert_ne!(
+            cipher.blind("keyword"),
+            create_cipher(&[&[2; KEY_LEN]]).blind("keyword")
+        );
+    }
+
+    #[test]
+    fn blind_all() {
+        let old = create_cipher(&[&[1; KEY_LEN]]);
+        let new = create_cipher(&[&[2; KEY_LEN], &[1; KEY_LEN]]);
+        let values = [String::from("a"), String::from("b")];
+        assert_eq!(
+            new.blind_all(&values),
+            [
+                vec![new.blind("a"), new.blind("b")],
+                vec![old.blind("a"), old.blind("b")]
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_keys() {
+        let config = EncryptionConfig {
+            keys: vec![BASE64.encode([1; 16])],
+            blind_keywords: false,
+        };
+        assert!(matches!(Cipher::new(&config), Err(CipherError::KeyLength)));
+        let config = EncryptionConfig {
+            keys: vec![String::from("not base64!")],
+            blind_keywords: false,
+        };
+        assert!(matches!(Cipher::new(&config), Err(CipherError::Decode(_))));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufReader, Error as IoError},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+use deadpool_postgres::{
+    BuildError as PgPoolBuildError, Manager as PgManager, ManagerConfig as PgManagerConfig, Pool as PgPool,
+    RecyclingMethod, Runtime,
+};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, Error as TlsError, RootCertStore, SignatureScheme,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::{CryptoProvider, ring, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+};
+use tokio_postgres::{Config as PgConfig, Error as PgError, NoTls as PgNoTls, config::SslMode as PgSslMode};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+use crate::config::{DatabasePoolConfig, DatabaseTlsConfig, SslMode};
+
+/// Creates a pool of PostgreSQL connections
+///
+/// Connections are established on demand, so a broken connection is replaced
+/// by a new one when the database becomes available again.
+pub fn create_pool(
+    database_url: &str,
+    pool_config: &DatabasePoolConfig,
+    tls_config: Option<&DatabaseTlsConfig>,
+) -> Result<PgPool, DatabaseError> {
+    let mut pg_config: PgConfig = database_url.parse().map_err(DatabaseError::Url)?;
+    pg_config.connect_timeout(Duration::from_secs(pool_config.connect_timeout));
+    let manager_config = PgManagerConfig {
+        recycling_method: if pool_config.health_check {
+            RecyclingMethod::Verified
+        } else {
+            RecyclingMethod::Fast
+        },
+    };
+    let manager = match tls_config {
+        Some(tls_config) => {
+            pg_config.ssl_mode(match tls_config.mode {
+                SslMode::Disable => PgSslMode::Disable,
+                SslMode::Prefer => PgSslMode::Prefer,
+                SslMode::Require | SslMode::VerifyCa | SslMode::VerifyFull => PgSslMode::Require,
+            });
+            let tls = MakeRustlsConnect::new(create_tls_config(tls_config)?);
+            PgManager::from_config(pg_config, tls, manager_config)
+        }
+        None => PgManager::from_config(pg_config, PgNoTls, manager_config),
+    };
+    PgPool::builder(manager)
+        .max_size(pool_config.size)
+        .runtime(Runtime::Tokio1)
+        .wait_timeout(Some(Duration::from_secs(pool_config.wait_timeout)))
+        .create_timeout(Some(Duration::from_secs(pool_config.connect_timeout)))
+        .recycle_timeout(Some(Duration::from_secs(pool_config.recycle_timeout)))
+        .build()
+        .map_err(DatabaseError::Build)
+}
+
+/// Creates a TLS config following `sslmode` of libpq
+///
+/// `prefer` and `require` do not verify the server unless a root certificate is given,
+/// `verify_ca` verifies the certificate chain only, `verify_full` verifies the host name as well.
+/// Without a root certificate, the Mozilla root certificates are trusted.
+fn create_tls_config(config: &DatabaseTlsConfig) -> Result<ClientConfig, DatabaseError> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(DatabaseError::Tls)?;
+
+    let mut roots = RootCertStore::empty();
+    match config.root_cert {
+        Some(ref path) => {
+            for cert in read_certs(path)? {
+                roots.add(cert).map_err(DatabaseError::Tls)?;
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let webpki_verifier = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .build()
+        .map_err(|err| DatabaseError::Tls(TlsError::General(err.to_string())))?;
+    let verify = match config.mode {
+        SslMode::Disable | SslMode::Prefer | SslMode::Require => config.root_cert.is_some(),
+        SslMode::VerifyCa | SslMode::VerifyFull => true,
+    };
+    let verifier = Arc::new(Verifier {
+        inner: webpki_verifier,
+        provider,
+        verify,
+        verify_name: config.mode == SslMode::VerifyFull,
+    });
+    let builder = builder.dangerous().with_custom_certificate_verifier(verifier);
+
+    match (&config.client_cert, &config.client_key) {
+        (Some(cert_path), Some(key_path)) => builder
+            .with_client_auth_cert(read_certs(cert_path)?, read_key(key_path)?)
+            .map_err(DatabaseError::Tls),
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err(DatabaseError::ClientCert),
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, DatabaseError> {
+    let file = File::open(path).map_err(|err| DatabaseError::ReadFile(path.display().to_string(), err))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, IoError>>()
+        .map_err(|err| DatabaseError::ReadFile(path.display().to_string(), err))
+}
+
+fn read_key(path: &Path) -> Result<PrivateKeyDer<'static>, DatabaseError> {
+    let file = File::open(path).map_err(|err| DatabaseError::ReadFile(path.display().to_string(), err))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|err| DatabaseError::ReadFile(path.display().to_string(), err))?
+        .ok_or_else(|| DatabaseError::NoKey(path.display().to_string()))
+}
+
+/// Verifies a server certificate according to `sslmode`
+///
+/// Signatures of the handshake are always verified.
+#[derive(Debug)]
+struct Verifier {
+    inner: Arc<WebPkiServerVerifier>,
+    provider: Arc<CryptoProvider>,
+    /// Whether the certificate chain is verified
+    verify: bool,
+    /// Whether the host name is verified
+    verify_name: bool,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, TlsError> {
+        if !self.verify {
+            return Ok(ServerCertVerified::assertion());
+        }
+        match self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+        {
+            Err(TlsError::InvalidCertificate(CertificateError::NotValidForName)) if !self.verify_name => {
+                Ok(ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, TlsError> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Build(PgPoolBuildError),
+    ClientCert,
+    NoKey(String),
+    ReadFile(String, IoError),
+    Tls(TlsError),
+    Url(PgError),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::DatabaseError::*;
+        match self {
+            Build(err) => write!(out, "can not create connection pool: {err}"),
+            ClientCert => write!(out, "client certificate and key must be set together"),
+            NoKey(path) => write!(out, "private key is not found in {path}"),
+            ReadFile(path, err) => write!(out, "can not read {path}: {err}"),
+            Tls(err) => write!(out, "TLS: {err}"),
+            Url(err) => write!(out, "invalid database URL: {err}"),
+        }
+    }
+}
+
+impl Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::DatabaseError::*;
+        match self {
+            Build(err) => Some(err),
+            ClientCert => None,
+            NoKey(_) => None,
+            ReadFile(_, err) => Some(err),
+            Tls(err) => Some(err),
+            Url(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn create_config(mode: SslMode) -> DatabaseTlsConfig {
+        DatabaseTlsConfig {
+            mode,
+            root_cert: None,
+            client_cert: None,
+            client_key: None,
+        }
+    }
+
+    #[test]
+    fn tls_config() {
+        for mode in [SslMode::Require, SslMode::VerifyCa, SslMode::VerifyFull] {
+            assert!(create_tls_config(&create_config(mode)).is_ok());
+        }
+        let config = DatabaseTlsConfig {
+            client_cert: Some(PathBuf::from("client.pem")),
+            ..create_config(SslMode::VerifyFull)
+        };
+        assert!(matches!(create_tls_config(&config), Err(DatabaseError::ClientCert)));
+        let config = DatabaseTlsConfig {
+            root_cert: Some(PathBuf::from("/nonexistent/root.pem")),
+            ..create_config(SslMode::VerifyFull)
+        };
+        assert!(matches!(create_tls_config(&config), Err(DatabaseError::ReadFile(_, _))));
+    }
+}
@@ -0,0 +1,158 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use carapax::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use serde::{Deserialize, Serialize};
+
+const SEPARATOR: &str = "|";
+const INLINE_PREFIX: &str = "inline:";
+const URL_SCHEMES: [&str; 3] = ["http://", "https://", "tg://"];
+
+/// A button attached to a note
+///
+/// Buttons are parsed from lines like `Open runbook | https://example.com`
+/// or `Share | inline: greet` for a switch inline query button.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteButton {
+    SwitchInlineQuery { text: String, query: String },
+    Url { text: String, url: String },
+}
+
+impl NoteButton {
+    /// Parses buttons from a text, one button per line
+    pub fn parse_list(text: &str) -> Result<Vec<Self>, NoteButtonError> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(idx, line)| {
+                line.parse().map_err(|err: NoteButtonErrorKind| NoteButtonError {
+                    line: idx + 1,
+                    kind: err,
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_markup(buttons: &[Self]) -> Option<InlineKeyboardMarkup> {
+        if buttons.is_empty() {
+            None
+        } else {
+            Some(InlineKeyboardMarkup::from(
+                buttons
+                    .iter()
+                    .map(|button| [InlineKeyboardButton::from(button.clone())]),
+            ))
+        }
+    }
+}
+
+impl FromStr for NoteButton {
+    type Err = NoteButtonErrorKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (text, value) = s.split_once(SEPARATOR).ok_or(NoteButtonErrorKind::NoSeparator)?;
+        let (text, value) = (text.trim(), value.trim());
+        if text.is_empty() {
+            return Err(NoteButtonErrorKind::EmptyText);
+        }
+        let text = String::from(text);
+        if let Some(query) = value.strip_prefix(INLINE_PREFIX) {
+            Ok(Self::SwitchInlineQuery {
+                text,
+                query: String::from(query.trim()),
+            })
+        } else if URL_SCHEMES.iter().any(|scheme| value.starts_with(scheme)) {
+            Ok(Self::Url {
+                text,
+                url: String::from(value),
+            })
+        } else {
+            Err(NoteButtonErrorKind::BadValue)
+        }
+    }
+}
+
+impl From<NoteButton> for InlineKeyboardButton {
+    fn from(button: NoteButton) -> Self {
+        match button {
+            NoteButton::SwitchInlineQuery { text, query } => InlineKeyboardButton::for_switch_inline_query(text, query),
+            NoteButton::Url { text, url } => InlineKeyboardButton::for_url(text, url),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct NoteButtonError {
+    line: usize,
+    kind: NoteButtonErrorKind,
+}
+
+impl fmt::Display for NoteButtonError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl Error for NoteButtonError {}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum NoteButtonErrorKind {
+    BadValue,
+    EmptyText,
+    NoSeparator,
+}
+
+impl fmt::Display for NoteButtonErrorKind {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::NoteButtonErrorKind::*;
+        match self {
+            BadValue => write!(out, "value must be a URL or start with \"{INLINE_PREFIX}\""),
+            EmptyText => write!(out, "button text is empty"),
+            NoSeparator => write!(out, "text and value must be separated by \"{SEPARATOR}\""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_list() {
+        assert_eq!(
+            NoteButton::parse_list("Open runbook | https://example.com/runbook\n\n  Share | inline: greet  \n"),
+            Ok(vec![
+                NoteButton::Url {
+                    text: String::from("Open runbook"),
+                    url: String::from("https://example.com/runbook"),
+                },
+                NoteButton::SwitchInlineQuery {
+                    text: String::from("Share"),
+                    query: String::from("greet"),
+                },
+            ])
+        );
+        assert_eq!(
+            NoteButton::parse_list("Open | https://example.com\nBroken"),
+            Err(NoteButtonError {
+                line: 2,
+                kind: NoteButtonErrorKind::NoSeparator
+            })
+        );
+        assert_eq!(
+            NoteButton::parse_list(" | https://example.com"),
+            Err(NoteButtonError {
+                line: 1,
+                kind: NoteButtonErrorKind::EmptyText
+            })
+        );
+        assert_eq!(
+            NoteButton::parse_list("Open | example.com"),
+            Err(NoteButtonError {
+                line: 1,
+                kind: NoteButtonErrorKind::BadValue
+            })
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use time::{Duration, OffsetDateTime};
+use tokio_postgres::Row;
+
+pub const DEFAULT_DIGEST_SIZE: i32 = 3;
+pub const MAX_DIGEST_SIZE: i32 = 10;
+
+/// Periodic delivery of notes to a chat
+#[derive(Clone, Debug)]
+pub struct Digest {
+    chat_id: i64,
+    settings: DigestSettings,
+    next_at: OffsetDateTime,
+}
+
+impl Digest {
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    pub fn settings(&self) -> DigestSettings {
+        self.settings
+    }
+
+    pub fn next_at(&self) -> OffsetDateTime {
+        self.next_at
+    }
+
+    /// Returns the first scheduled time after `now`
+    pub fn following_at(&self, now: OffsetDateTime) -> OffsetDateTime {
+        let period = self.settings.schedule.as_duration();
+        let mut result = self.next_at + period;
+        if result <= now {
+            // Skip digests missed while the bot was not running
+            let missed = ((now - result).whole_seconds() / period.whole_seconds()) as i32 + 1;
+            result += period * missed;
+        }
+        result
+    }
+}
+
+impl TryFrom<Row> for Digest {
+    type Error = DigestError;
+
+    fn try_from(row: Row) -> Result<Self, Self::Error> {
+        let schedule: &str = row.get("schedule");
+        let mode: &str = row.get("mode");
+        Ok(Self {
+            chat_id: row.get("chat_id"),
+            settings: DigestSettings {
+                schedule: schedule.parse()?,
+                mode: mode.parse()?,
+                size: row.get("size"),
+            },
+            next_at: row.get("next_at"),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DigestSettings {
+    pub schedule: DigestSchedule,
+    pub mode: DigestMode,
+    pub size: i32,
+}
+
+impl DigestSettings {
+    /// Parses settings from command arguments, e.g. `weekly forgotten 5`
+    ///
+    /// A schedule is required, mode and size are optional and may go in any order.
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Self, DigestError> {
+        let mut schedule = None;
+        let mut mode = DigestMode::default();
+        let mut size = DEFAULT_DIGEST_SIZE;
+        for arg in args {
+            let arg = arg.as_ref();
+            if let Ok(value) = arg.parse() {
+                schedule = Some(value);
+            } else if let Ok(value) = arg.parse() {
+                mode = value;
+            } else if let Ok(value) = arg.parse::<i32>() {
+                if !(1..=MAX_DIGEST_SIZE).contains(&value) {
+                    return Err(DigestError::Size(value));
+                }
+                size = value;
+            } else {
+                return Err(DigestError::UnknownValue(String::from(arg)));
+            }
+        }
+        Ok(Self {
+            schedule: schedule.ok_or(DigestError::NoSchedule)?,
+            mode,
+            size,
+        })
+    }
+}
+
+impl fmt::Display for DigestSettings {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}, {} {} notes", self.schedule, self.size, self.mode)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DigestSchedule {
+    Daily,
+    Weekly,
+}
+
+impl DigestSchedule {
+    pub fn as_duration(self) -> Duration {
+        match self {
+            DigestSchedule::Daily => Duration::DAY,
+            DigestSchedule::Weekly => Duration::WEEK,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DigestSchedule::Daily => "daily",
+            DigestSchedule::Weekly => "weekly",
+        }
+    }
+}
+
+impl FromStr for DigestSchedule {
+    type Err = DigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "daily" => DigestSchedule::Daily,
+            "weekly" => DigestSchedule::Weekly,
+            _ => return Err(DigestError::UnknownValue(String::from(s))),
+        })
+    }
+}
+
+impl fmt::Display for DigestSchedule {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        out.write_str(self.as_str())
+    }
+}
+
+/// How notes are picked for a digest
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DigestMode {
+    /// Notes which were not used for the longest time
+    Forgotten,
+    #[default]
+    Random,
+}
+
+impl DigestMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DigestMode::Forgotten => "forgotten",
+            DigestMode::Random => "random",
+        }
+    }
+
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            DigestMode::Forgotten => "last_used_at ASC NULLS FIRST, usage_count ASC, id ASC",
+            DigestMode::Random => "random()",
+        }
+    }
+}
+
+impl FromStr for DigestMode {
+    type Err = DigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "forgotten" => DigestMode::Forgotten,
+            "random" => DigestMode::Random,
+            _ => return Err(DigestError::UnknownValue(String::from(s))),
+        })
+    }
+}
+
+impl fmt::Display for DigestMode {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        out.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum DigestError {
+    NoSchedule,
+    Size(i32),
+    UnknownValue(String),
+}
+
+impl fmt::Display for DigestError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::DigestError::*;
+        match self {
+            NoSchedule => write!(out, "schedule is required (daily or weekly)"),
+            Size(value) => write!(out, "size must be between 1 and {MAX_DIGEST_SIZE}, got {value}"),
+            UnknownValue(value) => write!(out, "unknown value: {value}"),
+        }
+    }
+}
+
+impl Error for DigestError {}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        assert_eq!(
+            DigestSettings::parse(&["daily"]),
+            Ok(DigestSettings {
+                schedule: DigestSchedule::Daily,
+                mode: DigestMode::Random,
+                size: DEFAULT_DIGEST_SIZE,
+            })
+        );
+        assert_eq!(
+            DigestSettings::parse(&["5", "Forgotten", "weekly"]),
+            Ok(DigestSettings {
+                schedule: DigestSchedule::Weekly,
+                mode: DigestMode::Forgotten,
+                size: 5,
+            })
+        );
+        assert_eq!(DigestSettings::parse(&["random"]), Err(DigestError::NoSchedule));
+        assert_eq!(DigestSettings::parse(&["daily", "0"]), Err(DigestError::Size(0)));
+        assert_eq!(
+            DigestSettings::parse(&["daily", "often"]),
+            Err(DigestError::UnknownValue(String::from("often")))
+        );
+    }
+
+    #[test]
+    fn following_at() {
+        let digest = Digest {
+            chat_id: 1,
+            settings: DigestSettings {
+                schedule: DigestSchedule::Daily,
+                mode: DigestMode::Random,
+                size: 1,
+            },
+            next_at: datetime!(2026-10-18 09:00 UTC),
+        };
+        assert_eq!(
+            digest.following_at(datetime!(2026-10-18 09:00:30 UTC)),
+            datetime!(2026-10-19 09:00 UTC)
+        );
+        assert_eq!(
+            digest.following_at(datetime!(2026-10-21 12:00 UTC)),
+            datetime!(2026-10-22 09:00 UTC)
+        );
+    }
+}
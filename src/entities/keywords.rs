@@ -1,4 +1,7 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(transparent)]
 pub struct Keywords {
     items: Vec<String>,
 }
@@ -7,6 +10,20 @@ impl Keywords {
     pub fn as_string(&self) -> String {
         self.items.join(" ")
     }
+
+    /// Whether every item of `other` is present, same as `@>` in SQL
+    pub fn contains_all(&self, other: &[&str]) -> bool {
+        other.iter().all(|item| self.items.iter().any(|x| x == item))
+    }
+
+    /// Appends keywords which are not present yet
+    pub fn merge(&mut self, other: &Keywords) {
+        for item in &other.items {
+            if !self.items.contains(item) {
+                self.items.push(item.clone());
+            }
+        }
+    }
 }
 
 impl<T, I> From<T> for Keywords
@@ -26,3 +43,23 @@ impl AsRef<[String]> for Keywords {
         self.items.as_ref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge() {
+        let mut keywords = Keywords::from(["a", "b"]);
+        keywords.merge(&Keywords::from(["b", "c", "c"]));
+        assert_eq!(keywords.as_ref(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn contains_all() {
+        let keywords = Keywords::from(["a", "b", "c"]);
+        assert!(keywords.contains_all(&["c", "a"]));
+        assert!(keywords.contains_all(&[]));
+        assert!(!keywords.contains_all(&["a", "d"]));
+    }
+}
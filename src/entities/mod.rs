@@ -1,9 +1,24 @@
 pub use self::{
+    button::NoteButton,
+    digest::{Digest, DigestError, DigestMode, DigestSettings},
     keywords::Keywords,
-    note::{NewNote, Note, NoteData, NoteDataError, NoteError},
-    note_info::NoteInfoList,
+    note::{DuplicateKey, NewNote, Note, NoteData, NoteDataError, NoteError, NoteKind},
+    note_file::NoteFile,
+    note_info::{LIST_PAGE_SIZE, NoteInfoList, NoteOrder},
+    note_query::NoteQuery,
+    period::Period,
+    reminder::{Reminder, parse_reminder_time},
+    template::Template,
 };
 
+mod button;
+mod digest;
 mod keywords;
 mod note;
+mod note_file;
 mod note_info;
+mod note_query;
+mod note_schema;
+mod period;
+mod reminder;
+mod template;
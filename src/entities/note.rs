@@ -7,88 +7,383 @@ use carapax::types::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{Error as JsonError, Value as JsonValue};
+use time::OffsetDateTime;
 use tokio_postgres::Row;
 
-use crate::entities::Keywords;
+use crate::{
+    config::Thumbnails,
+    crypto::{Cipher, CipherError, Envelope},
+    entities::{
+        Keywords, NoteButton, NoteFile, Template,
+        note_schema::{self, CURRENT_VERSION, NoteSchemaError},
+    },
+};
 
-#[derive(Debug)]
+const MAX_PREVIEW_LEN: usize = 100;
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct NewNote {
     data: NoteData,
     keywords: Keywords,
+    title: Option<String>,
+    description: Option<String>,
+    buttons: Vec<NoteButton>,
+    expires_at: Option<OffsetDateTime>,
 }
 
 impl NewNote {
-    pub fn data(&self) -> &NoteData {
-        &self.data
-    }
-
     pub fn keywords(&self) -> &Keywords {
         &self.keywords
     }
+
+    pub fn with_title(mut self, value: Option<String>) -> Self {
+        self.title = value;
+        self
+    }
+
+    pub fn with_description(mut self, value: Option<String>) -> Self {
+        self.description = value;
+        self
+    }
+
+    pub fn expires_at(&self) -> Option<OffsetDateTime> {
+        self.expires_at
+    }
+
+    pub fn with_buttons(mut self, value: Vec<NoteButton>) -> Self {
+        self.buttons = value;
+        self
+    }
+
+    pub fn with_expires_at(mut self, value: Option<OffsetDateTime>) -> Self {
+        self.expires_at = value;
+        self
+    }
+
+    /// Returns values of the columns which depend on encryption
+    pub fn encode(&self, cipher: Option<&Cipher>) -> Result<EncodedNote, NoteDataError> {
+        EncodedNote::new(
+            &self.data,
+            &self.keywords,
+            NoteText {
+                title: self.title.clone(),
+                description: self.description.clone(),
+            },
+            &self.buttons,
+            cipher,
+        )
+    }
+}
+
+/// Values of the `data`, `keywords`, `title` and `description` columns
+///
+/// Title and description of an encrypted note are stored in the envelope, so their columns are empty.
+pub struct EncodedNote {
+    pub data: JsonValue,
+    pub keywords: Vec<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+}
+
+impl EncodedNote {
+    fn new(
+        data: &NoteData,
+        keywords: &Keywords,
+        text: NoteText,
+        buttons: &[NoteButton],
+        cipher: Option<&Cipher>,
+    ) -> Result<Self, NoteDataError> {
+        let mut stored = StoredNoteData {
+            version: CURRENT_VERSION,
+            data: data.clone(),
+            buttons: buttons.to_vec(),
+            keywords: None,
+            text: NoteText::default(),
+        };
+        let cipher = match cipher {
+            Some(cipher) => cipher,
+            None => {
+                return Ok(Self {
+                    data: serde_json::to_value(stored).map_err(NoteDataError::Serialize)?,
+                    keywords: keywords.as_ref().to_vec(),
+                    title: text.title,
+                    description: text.description,
+                });
+            }
+        };
+        stored.text = text;
+        let keywords = if cipher.blind_keywords() {
+            // The column contains hashes only, so keywords are kept in the encrypted payload
+            stored.keywords = Some(keywords.clone());
+            keywords.as_ref().iter().map(|keyword| cipher.blind(keyword)).collect()
+        } else {
+            keywords.as_ref().to_vec()
+        };
+        let payload = serde_json::to_vec(&stored).map_err(NoteDataError::Serialize)?;
+        let sealed = SealedNoteData {
+            envelope: SealedEnvelope {
+                kind: data.kind().tag().to_string(),
+                duplicate: data.duplicate_key().map(|key| cipher.blind(&key.to_string())),
+                value: cipher.seal(&payload).map_err(NoteDataError::Encrypt)?,
+            },
+        };
+        Ok(Self {
+            data: serde_json::to_value(sealed).map_err(NoteDataError::Serialize)?,
+            keywords,
+            title: None,
+            description: None,
+        })
+    }
+}
+
+/// Representation of the `data` column
+#[derive(Deserialize, Serialize)]
+struct StoredNoteData {
+    /// Version of the format, see `note_schema`
+    ///
+    /// Contains the version a payload was stored with after decoding.
+    version: u64,
+    #[serde(flatten)]
+    data: NoteData,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    buttons: Vec<NoteButton>,
+    /// Plain keywords, present in encrypted notes when the `keywords` column is blinded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    keywords: Option<Keywords>,
+    /// Present in encrypted notes only, other notes use columns
+    #[serde(flatten)]
+    text: NoteText,
+}
+
+/// A title and a description of a note
+#[derive(Default, Deserialize, Serialize)]
+struct NoteText {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+impl StoredNoteData {
+    fn decode(value: JsonValue, cipher: Option<&Cipher>) -> Result<Self, NoteError> {
+        let payload = if value.get("envelope").is_none() {
+            value
+        } else {
+            let sealed: SealedNoteData = serde_json::from_value(value).map_err(NoteError::Deserialize)?;
+            let cipher = cipher.ok_or(NoteError::NoKey)?;
+            let payload = cipher.open(&sealed.envelope.value).map_err(NoteError::Decrypt)?;
+            serde_json::from_slice(&payload).map_err(NoteError::Deserialize)?
+        };
+        let payload = note_schema::upgrade(payload).map_err(NoteError::Schema)?;
+        let mut result: Self = serde_json::from_value(payload.value).map_err(NoteError::Deserialize)?;
+        result.version = payload.stored_version;
+        Ok(result)
+    }
+}
+
+/// Representation of the `data` column of an encrypted note
+///
+/// A kind and a blinded duplicate key are kept in clear,
+/// so notes can be filtered by kind and duplicates can be found without decryption.
+#[derive(Deserialize, Serialize)]
+struct SealedNoteData {
+    envelope: SealedEnvelope,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SealedEnvelope {
+    kind: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duplicate: Option<String>,
+    #[serde(flatten)]
+    value: Envelope,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum NoteData {
-    Animation { file_id: String },
-    Audio { file_id: String },
-    Document { file_id: String },
+    Animation(NoteFile),
+    Audio(NoteFile),
+    Document(NoteFile),
     Location { longitude: Float, latitude: Float },
-    Photo { file_id: String },
+    Photo(NoteFile),
     Text(String),
-    Video { file_id: String },
-    Voice { file_id: String },
+    Video(NoteFile),
+    Voice(NoteFile),
 }
 
 impl NoteData {
     pub fn into_new(self, keywords: Keywords) -> NewNote {
-        NewNote { data: self, keywords }
+        NewNote {
+            data: self,
+            keywords,
+            title: None,
+            description: None,
+            buttons: Vec::new(),
+            expires_at: None,
+        }
+    }
+
+    pub fn kind(&self) -> NoteKind {
+        match self {
+            Self::Animation(_) => NoteKind::Animation,
+            Self::Audio(_) => NoteKind::Audio,
+            Self::Document(_) => NoteKind::Document,
+            Self::Location { .. } => NoteKind::Location,
+            Self::Photo(_) => NoteKind::Photo,
+            Self::Text(_) => NoteKind::Text,
+            Self::Video(_) => NoteKind::Video,
+            Self::Voice(_) => NoteKind::Voice,
+        }
+    }
+
+    /// Returns a key which is equal for notes with the same content
+    ///
+    /// Media is compared by `file_unique_id`, which stays the same across bots,
+    /// texts are compared case-insensitively with whitespaces collapsed.
+    pub fn duplicate_key(&self) -> Option<DuplicateKey> {
+        match self {
+            Self::Text(text) => Some(DuplicateKey::Text(normalize_text(text))),
+            _ => self
+                .file()
+                .and_then(|file| file.file_unique_id.clone())
+                .map(|file_unique_id| DuplicateKey::File {
+                    kind: self.kind(),
+                    file_unique_id,
+                }),
+        }
+    }
+
+    /// Returns a file of a media note
+    pub fn file(&self) -> Option<&NoteFile> {
+        match self {
+            Self::Animation(file)
+            | Self::Audio(file)
+            | Self::Document(file)
+            | Self::Photo(file)
+            | Self::Video(file)
+            | Self::Voice(file) => Some(file),
+            Self::Location { .. } | Self::Text(_) => None,
+        }
     }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DuplicateKey {
+    File { kind: NoteKind, file_unique_id: String },
+    Text(String),
+}
 
-    pub fn as_json(&self) -> Result<JsonValue, NoteDataError> {
-        serde_json::to_value(self).map_err(NoteDataError::Serialize)
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::File { kind, file_unique_id } => write!(out, "file:{kind}:{file_unique_id}"),
+            Self::Text(text) => write!(out, "text:{text}"),
+        }
     }
 }
 
+/// Lowercases a text and collapses its whitespaces
+///
+/// Must be consistent with the normalization in `NotesService::find_duplicate`.
+fn normalize_text(text: &str) -> String {
+    text.split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
 impl TryFrom<MessageData> for NoteData {
     type Error = NoteDataError;
 
     fn try_from(data: MessageData) -> Result<Self, Self::Error> {
         Ok(match data {
-            MessageData::Animation(animation) => Self::Animation {
-                file_id: animation.file_id,
-            },
-            MessageData::Audio(audio) => Self::Audio {
-                file_id: audio.data.file_id,
-            },
-            MessageData::Document(document) => Self::Document {
-                file_id: document.data.file_id,
-            },
+            MessageData::Animation(animation) => Self::Animation(animation.into()),
+            MessageData::Audio(audio) => Self::Audio(audio.data.into()),
+            MessageData::Document(document) => Self::Document(document.data.into()),
             MessageData::Location(location) => Self::Location {
                 latitude: location.latitude,
                 longitude: location.longitude,
             },
-            MessageData::Photo(photo) => Self::Photo {
-                file_id: photo
+            MessageData::Photo(photo) => Self::Photo(
+                photo
                     .data
                     .into_iter()
                     .max_by(|x, y| (x.width, x.height).cmp(&(y.width, y.height)))
-                    .map(|x| x.file_id)
-                    .ok_or(NoteDataError::PhotoNotFound)?,
-            },
+                    .ok_or(NoteDataError::PhotoNotFound)?
+                    .into(),
+            ),
             MessageData::Text(text) => Self::Text(text.data),
-            MessageData::Video(video) => Self::Video {
-                file_id: video.data.file_id,
-            },
-            MessageData::Voice(voice) => Self::Voice {
-                file_id: voice.data.file_id,
-            },
+            MessageData::Video(video) => Self::Video(video.data.into()),
+            MessageData::Voice(voice) => Self::Voice(voice.data.into()),
             _ => return Err(NoteDataError::UnsupportedMessage),
         })
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteKind {
+    Animation,
+    Audio,
+    Document,
+    Location,
+    Photo,
+    Text,
+    Video,
+    Voice,
+}
+
+impl NoteKind {
+    pub const ALL: [NoteKind; 8] = [
+        NoteKind::Animation,
+        NoteKind::Audio,
+        NoteKind::Document,
+        NoteKind::Location,
+        NoteKind::Photo,
+        NoteKind::Text,
+        NoteKind::Video,
+        NoteKind::Voice,
+    ];
+
+    /// Returns a key of the serialized `NoteData` variant
+    pub fn tag(self) -> &'static str {
+        use self::NoteKind::*;
+        match self {
+            Animation => "Animation",
+            Audio => "Audio",
+            Document => "Document",
+            Location => "Location",
+            Photo => "Photo",
+            Text => "Text",
+            Video => "Video",
+            Voice => "Voice",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        use self::NoteKind::*;
+        match self {
+            Animation => "animation",
+            Audio => "audio",
+            Document => "document",
+            Location => "location",
+            Photo => "photo",
+            Text => "text",
+            Video => "video",
+            Voice => "voice",
+        }
+    }
+}
+
+impl fmt::Display for NoteKind {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        write!(out, "{}", self.as_str())
+    }
+}
+
 #[derive(Debug)]
 pub enum NoteDataError {
+    Encrypt(CipherError),
     PhotoNotFound,
     Serialize(JsonError),
     UnsupportedMessage,
@@ -98,6 +393,7 @@ impl fmt::Display for NoteDataError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::NoteDataError::*;
         match self {
+            Encrypt(err) => write!(out, "encrypt note data: {err}"),
             PhotoNotFound => write!(out, "could not find photo"),
             Serialize(err) => write!(out, "serialize note data: {err}"),
             UnsupportedMessage => write!(out, "can not create note data from provided message"),
@@ -109,6 +405,7 @@ impl Error for NoteDataError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         use self::NoteDataError::*;
         match self {
+            Encrypt(err) => Some(err),
             PhotoNotFound => None,
             Serialize(err) => Some(err),
             UnsupportedMessage => None,
@@ -121,51 +418,202 @@ pub struct Note {
     id: i32,
     data: NoteData,
     keywords: Keywords,
+    title: Option<String>,
+    description: Option<String>,
+    buttons: Vec<NoteButton>,
+    expires_at: Option<OffsetDateTime>,
+    archive_path: Option<String>,
+    stored_version: u64,
 }
 
-impl TryFrom<Row> for Note {
-    type Error = NoteError;
+impl Note {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn data(&self) -> &NoteData {
+        &self.data
+    }
+
+    pub fn keywords(&self) -> &Keywords {
+        &self.keywords
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn buttons(&self) -> &[NoteButton] {
+        &self.buttons
+    }
+
+    pub fn expires_at(&self) -> Option<OffsetDateTime> {
+        self.expires_at
+    }
 
-    fn try_from(row: Row) -> Result<Self, Self::Error> {
-        let data: JsonValue = row.get("data");
-        let keywords: Vec<String> = row.get("keywords");
+    /// Returns a key of the archived file, see `ArchiveService`
+    pub fn archive_path(&self) -> Option<&str> {
+        self.archive_path.as_deref()
+    }
+
+    pub fn with_data(mut self, data: NoteData) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Whether the note is stored in a format older than the current one
+    pub fn is_outdated(&self) -> bool {
+        self.stored_version < CURRENT_VERSION
+    }
+
+    pub fn with_keywords(mut self, keywords: Keywords) -> Self {
+        self.keywords = keywords;
+        self
+    }
+
+    /// Returns values of the columns which depend on encryption
+    pub fn encode(&self, cipher: Option<&Cipher>) -> Result<EncodedNote, NoteDataError> {
+        EncodedNote::new(
+            &self.data,
+            &self.keywords,
+            NoteText {
+                title: self.title.clone(),
+                description: self.description.clone(),
+            },
+            &self.buttons,
+            cipher,
+        )
+    }
+
+    /// Creates a note from a row, encrypted notes require a cipher
+    pub fn decode(row: Row, cipher: Option<&Cipher>) -> Result<Self, NoteError> {
+        let data = StoredNoteData::decode(row.get("data"), cipher)?;
+        let keywords = match data.keywords {
+            Some(keywords) => keywords,
+            None => Keywords::from(row.get::<_, Vec<String>>("keywords")),
+        };
         Ok(Self {
             id: row.get("id"),
-            data: serde_json::from_value(data).map_err(NoteError::Deserialize)?,
-            keywords: Keywords::from(keywords),
+            data: data.data,
+            keywords,
+            // Notes encrypted before titles were moved into the envelope keep them in columns
+            title: data.text.title.or_else(|| row.get("title")),
+            description: data.text.description.or_else(|| row.get("description")),
+            buttons: data.buttons,
+            expires_at: row.get("expires_at"),
+            archive_path: row.get("archive_path"),
+            stored_version: data.version,
         })
     }
+
+    /// Returns whether the note is a text with placeholders
+    pub fn is_template(&self) -> bool {
+        matches!(self.data, NoteData::Text(ref text) if Template::new(text).has_placeholders())
+    }
+
+    /// Fills placeholders of a text note using the given arguments
+    pub fn with_arguments<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+        if let NoteData::Text(ref text) = self.data {
+            self.data = NoteData::Text(Template::new(text).render(args));
+        }
+        self
+    }
 }
 
-impl From<Note> for InlineQueryResult {
-    fn from(note: Note) -> Self {
-        let id = format!("{}", note.id);
-        let title = note.keywords.as_string();
-        match note.data {
-            NoteData::Animation { file_id } => InlineQueryResultCachedGif::new(file_id, id).with_title(title).into(),
-            NoteData::Audio { file_id } => InlineQueryResultCachedDocument::new(file_id, id, title).into(),
-            NoteData::Document { file_id } => InlineQueryResultCachedDocument::new(file_id, id, title).into(),
+/// Calls a setter of an inline query result when the value is present
+macro_rules! with_optional {
+    ($result:expr, $setter:ident, $value:expr) => {
+        match $value {
+            Some(value) => $result.$setter(value),
+            None => $result,
+        }
+    };
+}
+
+impl Note {
+    pub fn into_inline_query_result(self, thumbnails: &Thumbnails) -> InlineQueryResult {
+        let id = format!("{}", self.id);
+        let title = self.title.unwrap_or_else(|| self.keywords.as_string());
+        let description = self.description;
+        let markup = NoteButton::to_markup(&self.buttons);
+        match self.data {
+            NoteData::Animation(file) => {
+                let result = InlineQueryResultCachedGif::new(file.file_id, id).with_title(title);
+                with_optional!(result, with_reply_markup, markup).into()
+            }
+            NoteData::Audio(file) | NoteData::Document(file) => {
+                let result = InlineQueryResultCachedDocument::new(file.file_id, id, title);
+                let result = with_optional!(result, with_description, description);
+                with_optional!(result, with_reply_markup, markup).into()
+            }
             NoteData::Location { latitude, longitude } => {
-                InlineQueryResultLocation::new(id, latitude, longitude, title).into()
+                // Location results have no description, so a place name or coordinates are shown in the title
+                let place = description.unwrap_or_else(|| format!("{latitude:.5}, {longitude:.5}"));
+                let result = InlineQueryResultLocation::new(id, latitude, longitude, format!("{title} · {place}"));
+                let result = with_optional!(result, with_thumbnail_url, thumbnails.location.clone());
+                with_optional!(result, with_reply_markup, markup).into()
+            }
+            NoteData::Photo(file) => {
+                let result = InlineQueryResultCachedPhoto::new(id, file.file_id).with_title(title);
+                let result = with_optional!(result, with_description, description);
+                with_optional!(result, with_reply_markup, markup).into()
+            }
+            NoteData::Text(text) => {
+                let description = description.unwrap_or_else(|| preview(&text));
+                let result = InlineQueryResultArticle::new(id, text, title).with_description(description);
+                let result = with_optional!(result, with_thumbnail_url, thumbnails.text.clone());
+                with_optional!(result, with_reply_markup, markup).into()
+            }
+            NoteData::Video(file) => {
+                let result = InlineQueryResultCachedVideo::new(id, title, file.file_id);
+                let result = with_optional!(result, with_description, description);
+                with_optional!(result, with_reply_markup, markup).into()
+            }
+            NoteData::Voice(file) => {
+                let result = InlineQueryResultCachedVoice::new(id, title, file.file_id);
+                with_optional!(result, with_reply_markup, markup).into()
             }
-            NoteData::Photo { file_id } => InlineQueryResultCachedPhoto::new(id, file_id).with_title(title).into(),
-            NoteData::Text(text) => InlineQueryResultArticle::new(id, text, title).into(),
-            NoteData::Video { file_id } => InlineQueryResultCachedVideo::new(id, title, file_id).into(),
-            NoteData::Voice { file_id } => InlineQueryResultCachedVoice::new(id, title, file_id).into(),
         }
     }
 }
 
+/// Returns a single line preview of a text, whitespaces are collapsed
+fn preview(text: &str) -> String {
+    let mut result = String::new();
+    for (idx, word) in text.split_whitespace().enumerate() {
+        if idx > 0 {
+            result.push(' ');
+        }
+        result.push_str(word);
+        if result.chars().count() > MAX_PREVIEW_LEN {
+            result = result.chars().take(MAX_PREVIEW_LEN - 1).collect();
+            result.push('…');
+            break;
+        }
+    }
+    result
+}
+
 #[derive(Debug)]
 pub enum NoteError {
+    Decrypt(CipherError),
     Deserialize(JsonError),
+    NoKey,
+    Schema(NoteSchemaError),
 }
 
 impl fmt::Display for NoteError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::NoteError::*;
         match self {
+            Decrypt(err) => write!(out, "decrypt note: {err}"),
             Deserialize(err) => write!(out, "deserialize note: {err}"),
+            NoKey => write!(out, "note is encrypted, but encryption is not configured"),
+            Schema(err) => write!(out, "upgrade note: {err}"),
         }
     }
 }
@@ -174,7 +622,216 @@ impl Error for NoteError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         use self::NoteError::*;
         match self {
+            Decrypt(err) => Some(err),
             Deserialize(err) => Some(err),
+            NoKey => None,
+            Schema(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_preview() {
+        assert_eq!(preview("  Hello,\n\tworld!  "), "Hello, world!");
+        assert_eq!(preview(""), "");
+        let long_text = "word ".repeat(MAX_PREVIEW_LEN);
+        let result = preview(&long_text);
+        assert_eq!(result.chars().count(), MAX_PREVIEW_LEN);
+        assert!(result.ends_with('…'));
+    }
+
+    #[test]
+    fn duplicate_key() {
+        assert_eq!(
+            NoteData::Text(String::from("  Hello,\n\tWORLD!  ")).duplicate_key(),
+            Some(DuplicateKey::Text(String::from("hello, world!")))
+        );
+        assert_eq!(
+            NoteData::Photo(NoteFile {
+                file_id: String::from("file-id"),
+                file_unique_id: Some(String::from("unique-id")),
+                ..Default::default()
+            })
+            .duplicate_key(),
+            Some(DuplicateKey::File {
+                kind: NoteKind::Photo,
+                file_unique_id: String::from("unique-id"),
+            })
+        );
+        assert_eq!(
+            NoteData::Photo(NoteFile {
+                file_id: String::from("file-id"),
+                ..Default::default()
+            })
+            .duplicate_key(),
+            None
+        );
+    }
+
+    #[test]
+    fn stored_note_data() {
+        for (data, buttons, json) in [
+            (
+                NoteData::Text(String::from("text")),
+                vec![],
+                serde_json::json!({"Text": "text", "version": CURRENT_VERSION}),
+            ),
+            (
+                NoteData::Photo(NoteFile {
+                    file_id: String::from("file-id"),
+                    mime_type: Some(String::from("image/jpeg")),
+                    ..Default::default()
+                }),
+                vec![NoteButton::Url {
+                    text: String::from("Open"),
+                    url: String::from("https://example.com"),
+                }],
+                serde_json::json!({
+                    "Photo": {"file_id": "file-id", "mime_type": "image/jpeg"},
+                    "buttons": [{"url": {"text": "Open", "url": "https://example.com"}}],
+                    "version": CURRENT_VERSION
+                }),
+            ),
+        ] {
+            let new_note = data
+                .clone()
+                .into_new(Keywords::from(["k"]))
+                .with_buttons(buttons.clone());
+            let encoded = new_note.encode(None).unwrap();
+            assert_eq!(encoded.data, json);
+            assert_eq!(encoded.keywords, ["k"]);
+            let stored = StoredNoteData::decode(json, None).unwrap();
+            assert_eq!(stored.data, data);
+            assert_eq!(stored.buttons, buttons);
+            assert_eq!(stored.keywords, None);
+            assert_eq!(stored.version, CURRENT_VERSION);
+        }
+    }
+
+    #[test]
+    fn historical_note_data() {
+        fn file(kind: &str) -> NoteFile {
+            NoteFile {
+                file_id: format!("file-{kind}"),
+                ..Default::default()
+            }
+        }
+
+        fn with_unique_id(kind: &str) -> NoteFile {
+            NoteFile {
+                file_unique_id: Some(format!("unique-{kind}")),
+                ..file(kind)
+            }
+        }
+
+        fn with_metadata(kind: &str) -> NoteFile {
+            NoteFile {
+                file_name: Some(format!("{kind}.bin")),
+                mime_type: Some(String::from("application/octet-stream")),
+                file_size: Some(1024),
+                ..with_unique_id(kind)
+            }
+        }
+
+        let buttons = vec![
+            NoteButton::Url {
+                text: String::from("Open"),
+                url: String::from("https://example.com"),
+            },
+            NoteButton::SwitchInlineQuery {
+                text: String::from("Share"),
+                query: String::from("greet"),
+            },
+        ];
+        for (fixture, stored_version, create_file, buttons) in [
+            (
+                include_str!("fixtures/note_data/v0_baseline.json"),
+                0,
+                file as fn(&str) -> NoteFile,
+                vec![],
+            ),
+            (include_str!("fixtures/note_data/v0_buttons.json"), 0, file, buttons),
+            (
+                include_str!("fixtures/note_data/v0_file_unique_id.json"),
+                0,
+                with_unique_id,
+                vec![],
+            ),
+            (
+                include_str!("fixtures/note_data/v0_file_metadata.json"),
+                0,
+                with_metadata,
+                vec![],
+            ),
+            (include_str!("fixtures/note_data/v1.json"), 1, with_metadata, vec![]),
+        ] {
+            let items: Vec<JsonValue> = serde_json::from_str(fixture).unwrap();
+            assert_eq!(items.len(), NoteKind::ALL.len());
+            for (item, kind) in items.into_iter().zip(NoteKind::ALL) {
+                let expected = match kind {
+                    NoteKind::Animation => NoteData::Animation(create_file(kind.as_str())),
+                    NoteKind::Audio => NoteData::Audio(create_file(kind.as_str())),
+                    NoteKind::Document => NoteData::Document(create_file(kind.as_str())),
+                    NoteKind::Location => NoteData::Location {
+                        latitude: 1.5,
+                        longitude: 2.5,
+                    },
+                    NoteKind::Photo => NoteData::Photo(create_file(kind.as_str())),
+                    NoteKind::Text => NoteData::Text(String::from("text")),
+                    NoteKind::Video => NoteData::Video(create_file(kind.as_str())),
+                    NoteKind::Voice => NoteData::Voice(create_file(kind.as_str())),
+                };
+                let stored = StoredNoteData::decode(item, None).unwrap();
+                assert_eq!(stored.data, expected);
+                assert_eq!(stored.buttons, buttons);
+                assert_eq!(stored.version, stored_version);
+            }
         }
     }
+
+    #[test]
+    fn encrypted_note_data() {
+        use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+
+        use crate::config::EncryptionConfig;
+
+        let cipher = Cipher::new(&EncryptionConfig {
+            keys: vec![BASE64.encode([1; 32])],
+            blind_keywords: true,
+        })
+        .unwrap();
+        let data = NoteData::Text(String::from("Secret"));
+        let new_note = data
+            .clone()
+            .into_new(Keywords::from(["k"]))
+            .with_title(Some(String::from("Secret title")))
+            .with_description(Some(String::from("Secret description")));
+        let plain = new_note.encode(None).unwrap();
+        assert_eq!(plain.title.as_deref(), Some("Secret title"));
+        assert_eq!(plain.description.as_deref(), Some("Secret description"));
+        let encoded = new_note.encode(Some(&cipher)).unwrap();
+        assert_eq!(encoded.keywords, [cipher.blind("k")]);
+        assert_eq!(encoded.title, None);
+        assert_eq!(encoded.description, None);
+        assert_eq!(encoded.data["envelope"]["kind"], "Text");
+        assert_eq!(
+            encoded.data["envelope"]["duplicate"],
+            cipher.blind(&data.duplicate_key().unwrap().to_string())
+        );
+        assert!(!encoded.data.to_string().contains("Secret"));
+
+        let stored = StoredNoteData::decode(encoded.data.clone(), Some(&cipher)).unwrap();
+        assert_eq!(stored.data, data);
+        assert_eq!(stored.keywords, Some(Keywords::from(["k"])));
+        assert_eq!(stored.text.title.as_deref(), Some("Secret title"));
+        assert_eq!(stored.text.description.as_deref(), Some("Secret description"));
+        assert!(matches!(
+            StoredNoteData::decode(encoded.data, None),
+            Err(NoteError::NoKey)
+        ));
+    }
 }
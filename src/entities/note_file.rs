@@ -0,0 +1,180 @@
+use carapax::types::{Animation, Audio, Document, Integer, PhotoSize, Video, Voice};
+use serde::{Deserialize, Serialize};
+
+const SIZE_UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+
+/// A file of a media note
+///
+/// Everything except `file_id` is optional, notes created before
+/// the metadata was introduced contain the identifier only.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct NoteFile {
+    pub file_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_unique_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<Integer>,
+    /// Duration in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Integer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<Integer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<Integer>,
+}
+
+impl NoteFile {
+    /// Returns a short human readable description of the file,
+    /// e.g. `report.pdf · application/pdf · 1.2 MB`
+    pub fn summary(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(ref file_name) = self.file_name {
+            parts.push(file_name.clone());
+        }
+        if let Some(ref mime_type) = self.mime_type {
+            parts.push(mime_type.clone());
+        }
+        if let Some(file_size) = self.file_size {
+            parts.push(format_size(file_size));
+        }
+        if let Some(duration) = self.duration {
+            parts.push(format!("{}:{:02}", duration / 60, duration % 60));
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            parts.push(format!("{width}×{height}"));
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" · "))
+        }
+    }
+}
+
+fn format_size(value: Integer) -> String {
+    let mut size = value as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < SIZE_UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value} {}", SIZE_UNITS[0])
+    } else {
+        format!("{size:.1} {}", SIZE_UNITS[unit])
+    }
+}
+
+impl From<Animation> for NoteFile {
+    fn from(value: Animation) -> Self {
+        Self {
+            file_id: value.file_id,
+            file_unique_id: Some(value.file_unique_id),
+            file_name: value.file_name,
+            mime_type: value.mime_type,
+            file_size: value.file_size,
+            duration: Some(value.duration),
+            width: Some(value.width),
+            height: Some(value.height),
+        }
+    }
+}
+
+impl From<Audio> for NoteFile {
+    fn from(value: Audio) -> Self {
+        Self {
+            file_id: value.file_id,
+            file_unique_id: Some(value.file_unique_id),
+            file_name: value.file_name,
+            mime_type: value.mime_type,
+            file_size: value.file_size,
+            duration: Some(value.duration),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Document> for NoteFile {
+    fn from(value: Document) -> Self {
+        Self {
+            file_id: value.file_id,
+            file_unique_id: Some(value.file_unique_id),
+            file_name: value.file_name,
+            mime_type: value.mime_type,
+            file_size: value.file_size,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<PhotoSize> for NoteFile {
+    fn from(value: PhotoSize) -> Self {
+        Self {
+            file_id: value.file_id,
+            file_unique_id: Some(value.file_unique_id),
+            file_size: value.file_size,
+            width: Some(value.width),
+            height: Some(value.height),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Video> for NoteFile {
+    fn from(value: Video) -> Self {
+        Self {
+            file_id: value.file_id,
+            file_unique_id: Some(value.file_unique_id),
+            file_name: value.file_name,
+            mime_type: value.mime_type,
+            file_size: value.file_size,
+            duration: Some(value.duration),
+            width: Some(value.width),
+            height: Some(value.height),
+        }
+    }
+}
+
+impl From<Voice> for NoteFile {
+    fn from(value: Voice) -> Self {
+        Self {
+            file_id: value.file_id,
+            file_unique_id: Some(value.file_unique_id),
+            mime_type: value.mime_type,
+            file_size: value.file_size,
+            duration: Some(value.duration),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let file = NoteFile {
+            file_id: String::from("file-id"),
+            file_name: Some(String::from("report.pdf")),
+            mime_type: Some(String::from("application/pdf")),
+            file_size: Some(1_250_000),
+            ..Default::default()
+        };
+        assert_eq!(file.summary().unwrap(), "report.pdf · application/pdf · 1.2 MB");
+        let file = NoteFile {
+            file_id: String::from("file-id"),
+            file_size: Some(512),
+            duration: Some(125),
+            width: Some(1280),
+            height: Some(720),
+            ..Default::default()
+        };
+        assert_eq!(file.summary().unwrap(), "512 B · 2:05 · 1280×720");
+        assert_eq!(NoteFile::default().summary(), None);
+    }
+}
@@ -1,50 +1,102 @@
-use crate::entities::Keywords;
-use tokio_postgres::Row;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::{Keywords, Note, NoteFile},
+    render::{RenderMode, utf16_len},
+};
 
 const MAX_LIST_ITEM_LEN: usize = 4096;
+/// Number of notes on a page of `/list`
+pub const LIST_PAGE_SIZE: i64 = 20;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteOrder {
+    #[default]
+    Id,
+    Newest,
+    Used,
+}
+
+impl NoteOrder {
+    pub fn next(self) -> Self {
+        use self::NoteOrder::*;
+        match self {
+            Id => Newest,
+            Newest => Used,
+            Used => Id,
+        }
+    }
+
+    pub fn as_sql(self) -> &'static str {
+        use self::NoteOrder::*;
+        match self {
+            Id => "id ASC",
+            // Notes saved before timestamps were added share the time of the migration
+            Newest => "created_at DESC, id DESC",
+            Used => "usage_count DESC, id ASC",
+        }
+    }
+}
+
+impl fmt::Display for NoteOrder {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::NoteOrder::*;
+        out.write_str(match self {
+            Id => "id",
+            Newest => "newest",
+            Used => "most used",
+        })
+    }
+}
 
+/// A page of notes rendered as a single message
 pub struct NoteInfoList {
     items: Vec<NoteInfo>,
-    current_index: usize,
+    mode: RenderMode,
 }
 
 impl NoteInfoList {
     fn new(items: Vec<NoteInfo>) -> Self {
         Self {
             items,
-            current_index: 0,
+            mode: RenderMode::default(),
         }
     }
-}
 
-impl Iterator for NoteInfoList {
-    type Item = String;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let total_items = self.items.len();
-        if self.current_index >= total_items {
-            None
-        } else {
-            let mut size = 0;
-            let mut result = Vec::new();
-            for idx in self.current_index..total_items {
-                let item = self.items[idx].as_string();
-                let item_len = item.len();
-                if size + item_len > MAX_LIST_ITEM_LEN {
-                    break;
-                }
-                result.push(item);
-                size += item_len;
-                self.current_index += 1;
-            }
-            Some(result.join("\n"))
+    pub fn with_mode(mut self, mode: RenderMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Renders items joined using a line break
+    ///
+    /// An item longer than its share of the message is truncated, the share of a short item
+    /// goes to the following ones.
+    pub fn render(&self) -> String {
+        let mut result = Vec::with_capacity(self.items.len());
+        let mut remaining = MAX_LIST_ITEM_LEN;
+        for (idx, item) in self.items.iter().enumerate() {
+            let items_left = self.items.len() - idx;
+            let separators = items_left - 1 + usize::from(idx > 0);
+            let share = remaining.saturating_sub(separators) / items_left;
+            let item = item.render(self.mode, share);
+            remaining = remaining.saturating_sub(utf16_len(&item) + usize::from(idx > 0));
+            result.push(item);
         }
+        result.join("\n")
     }
 }
 
-impl From<Vec<Row>> for NoteInfoList {
-    fn from(rows: Vec<Row>) -> Self {
-        let items = rows.into_iter().map(NoteInfo::from).collect();
+impl From<Vec<Note>> for NoteInfoList {
+    fn from(notes: Vec<Note>) -> Self {
+        let items = notes.into_iter().map(NoteInfo::from).collect();
         Self::new(items)
     }
 }
@@ -53,25 +105,28 @@ impl From<Vec<Row>> for NoteInfoList {
 pub struct NoteInfo {
     id: i32,
     keywords: Keywords,
+    file: Option<String>,
 }
 
 impl NoteInfo {
-    fn as_string(&self) -> String {
-        let mut result = format!(r#"`{}` \- {}"#, self.id, self.keywords.as_string());
-        if result.len() > MAX_LIST_ITEM_LEN {
-            result = result.chars().take(MAX_LIST_ITEM_LEN - 3).collect();
-            result.push_str("...");
+    fn render(&self, mode: RenderMode, max_len: usize) -> String {
+        let prefix = format!("{} {} ", mode.code(&self.id.to_string()), mode.escape("-"));
+        let mut text = self.keywords.as_string();
+        if let Some(ref file) = self.file {
+            text.push_str(" · ");
+            text.push_str(file);
         }
-        result
+        let text = mode.truncate(&text, max_len.saturating_sub(utf16_len(&prefix)));
+        prefix + &text
     }
 }
 
-impl From<Row> for NoteInfo {
-    fn from(row: Row) -> Self {
-        let keywords: Vec<String> = row.get("keywords");
+impl From<Note> for NoteInfo {
+    fn from(note: Note) -> Self {
         Self {
-            id: row.get("id"),
-            keywords: Keywords::from(keywords),
+            id: note.id(),
+            file: note.data().file().and_then(NoteFile::summary),
+            keywords: note.keywords().clone(),
         }
     }
 }
@@ -87,9 +142,20 @@ mod tests {
         NoteInfo {
             id,
             keywords: keywords.into(),
+            file: None,
         }
     }
 
+    #[test]
+    fn note_info_list_file() {
+        let mut info = create_note_info(1, vec!["report"]);
+        info.file = Some(String::from("report.pdf · application/pdf · 1.2 MB"));
+        assert_eq!(
+            NoteInfoList::new(vec![info]).render(),
+            r"`1` \- report · report\.pdf · application/pdf · 1\.2 MB"
+        );
+    }
+
     #[test]
     fn note_info_list() {
         let list = NoteInfoList::new(vec![
@@ -97,14 +163,53 @@ mod tests {
             create_note_info(2, vec!["k3", "k4"]),
             create_note_info(3, vec!["k".repeat(MAX_LIST_ITEM_LEN * 2)]),
         ]);
-        let formatted_list: Vec<String> = list.collect();
         const PREFIX_LEN: usize = 7;
+        const ITEM_LEN: usize = 12;
+        // Short items leave their share to the last one
+        let last_len = MAX_LIST_ITEM_LEN - 2 * (ITEM_LEN + 1);
         assert_eq!(
-            formatted_list,
-            &[
-                String::from("`1` \\- k1 k2\n`2` \\- k3 k4"),
-                format!(r#"`3` \- {}..."#, "k".repeat(MAX_LIST_ITEM_LEN - 3 - PREFIX_LEN))
-            ]
-        )
+            list.render(),
+            format!(
+                "`1` \\- k1 k2\n`2` \\- k3 k4\n`3` \\- {}…",
+                "k".repeat(last_len - 1 - PREFIX_LEN)
+            )
+        );
+        assert!(NoteInfoList::new(Vec::new()).render().is_empty());
+    }
+
+    #[test]
+    fn note_info_list_escape() {
+        let list = NoteInfoList::new(vec![create_note_info(1, vec!["snake_case", "v1.0", "(x)", "a-b"])]);
+        assert_eq!(list.render(), r"`1` \- snake\_case v1\.0 \(x\) a\-b");
+
+        let list = NoteInfoList::new(vec![create_note_info(1, vec!["<b>", "&"])]).with_mode(RenderMode::Html);
+        assert_eq!(list.render(), "<code>1</code> - &lt;b&gt; &amp;");
+    }
+
+    #[test]
+    fn note_info_list_utf16() {
+        // Each item takes 1024 UTF-16 code units, but 2048 bytes
+        const PREFIX_LEN: usize = 7;
+        let keywords = "я".repeat(1024 - PREFIX_LEN);
+        let list = NoteInfoList::new((1..=5).map(|id| create_note_info(id, vec![keywords.clone()])).collect());
+        let rendered = list.render();
+        assert_eq!(rendered.lines().count(), 5);
+        assert!(rendered.lines().all(|line| line.ends_with('…')));
+        assert!(utf16_len(&rendered) <= MAX_LIST_ITEM_LEN);
+
+        let list = NoteInfoList::new(vec![create_note_info(1, vec!["😀".repeat(MAX_LIST_ITEM_LEN)])]);
+        assert_eq!(utf16_len(&list.render()), MAX_LIST_ITEM_LEN);
+    }
+
+    #[test]
+    fn note_info_list_page_size() {
+        let list = NoteInfoList::new(
+            (1..=LIST_PAGE_SIZE as i32)
+                .map(|id| create_note_info(id, vec!["k".repeat(MAX_LIST_ITEM_LEN)]))
+                .collect(),
+        );
+        let rendered = list.render();
+        assert_eq!(rendered.lines().count(), LIST_PAGE_SIZE as usize);
+        assert!(utf16_len(&rendered) <= MAX_LIST_ITEM_LEN);
     }
 }
@@ -0,0 +1,126 @@
+use std::{error::Error, fmt};
+
+use crate::entities::{Keywords, NoteData, NoteKind};
+
+const TYPE_PREFIX: &str = "type:";
+const MIME_PREFIX: &str = "mime:";
+
+/// Search query: keywords with optional filters
+///
+/// Filters are written as `type:document` (a kind of note)
+/// and `mime:pdf` (a part of a MIME type, case-insensitive),
+/// other words are keywords.
+#[derive(Debug, PartialEq)]
+pub struct NoteQuery {
+    pub keywords: Keywords,
+    pub kind: Option<NoteKind>,
+    pub mime_type: Option<String>,
+}
+
+impl NoteQuery {
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Self, NoteQueryError> {
+        let mut keywords = Vec::new();
+        let mut kind = None;
+        let mut mime_type = None;
+        for arg in args {
+            let arg = arg.as_ref();
+            if let Some(value) = arg.strip_prefix(TYPE_PREFIX) {
+                kind = Some(
+                    NoteKind::ALL
+                        .into_iter()
+                        .find(|x| x.as_str().eq_ignore_ascii_case(value))
+                        .ok_or_else(|| NoteQueryError::UnknownType(String::from(value)))?,
+                );
+            } else if let Some(value) = arg.strip_prefix(MIME_PREFIX) {
+                if value.is_empty() {
+                    return Err(NoteQueryError::EmptyMimeType);
+                }
+                mime_type = Some(String::from(value));
+            } else if !arg.is_empty() {
+                keywords.push(arg);
+            }
+        }
+        Ok(Self {
+            keywords: Keywords::from(keywords),
+            kind,
+            mime_type,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keywords.as_ref().is_empty() && self.kind.is_none() && self.mime_type.is_none()
+    }
+
+    /// Whether a note passes the `mime:` filter
+    pub fn matches_mime_type(&self, data: &NoteData) -> bool {
+        let Some(ref value) = self.mime_type else {
+            return true;
+        };
+        data.file()
+            .and_then(|file| file.mime_type.as_deref())
+            .is_some_and(|mime_type| mime_type.to_lowercase().contains(&value.to_lowercase()))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum NoteQueryError {
+    EmptyMimeType,
+    UnknownType(String),
+}
+
+impl fmt::Display for NoteQueryError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::NoteQueryError::*;
+        match self {
+            EmptyMimeType => write!(out, "{MIME_PREFIX} filter requires a value"),
+            UnknownType(value) => write!(
+                out,
+                "unknown type: {value}, expected one of: {}",
+                NoteKind::ALL.map(NoteKind::as_str).join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for NoteQueryError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::NoteFile;
+
+    #[test]
+    fn parse() {
+        let query = NoteQuery::parse(&["report", "type:Document", "mime:pdf", "2024"]).unwrap();
+        assert_eq!(query.keywords.as_ref(), ["report", "2024"]);
+        assert_eq!(query.kind, Some(NoteKind::Document));
+        assert_eq!(query.mime_type.as_deref(), Some("pdf"));
+        assert!(!query.is_empty());
+
+        let query = NoteQuery::parse(&["type:photo"]).unwrap();
+        assert!(query.keywords.as_ref().is_empty());
+        assert_eq!(query.kind, Some(NoteKind::Photo));
+
+        assert!(NoteQuery::parse::<&str>(&[]).unwrap().is_empty());
+        assert_eq!(
+            NoteQuery::parse(&["type:pdf"]),
+            Err(NoteQueryError::UnknownType(String::from("pdf")))
+        );
+        assert_eq!(NoteQuery::parse(&["mime:"]), Err(NoteQueryError::EmptyMimeType));
+    }
+
+    #[test]
+    fn matches_mime_type() {
+        let document = NoteData::Document(NoteFile {
+            mime_type: Some(String::from("application/PDF")),
+            ..NoteFile::default()
+        });
+        let text = NoteData::Text(String::from("pdf"));
+        let query = NoteQuery::parse(&["mime:pdf"]).unwrap();
+        assert!(query.matches_mime_type(&document));
+        assert!(!query.matches_mime_type(&text));
+        assert!(!query.matches_mime_type(&NoteData::Photo(NoteFile::default())));
+        let query = NoteQuery::parse(&["report"]).unwrap();
+        assert!(query.matches_mime_type(&text));
+    }
+}
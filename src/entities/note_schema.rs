@@ -0,0 +1,102 @@
+use std::{error::Error, fmt};
+
+use serde_json::{Map, Value as JsonValue};
+
+/// Version of the `data` column format written by this build
+pub const CURRENT_VERSION: u64 = 1;
+
+/// Key of the version in a stored payload, payloads without it have version 0
+const VERSION_KEY: &str = "version";
+
+type Upgrade = fn(&mut Map<String, JsonValue>);
+
+/// Steps upgrading a payload, a step at index N converts version N to N + 1
+///
+/// When the format changes, bump `CURRENT_VERSION`, add a step and a fixture of the previous format.
+const UPGRADES: [Upgrade; CURRENT_VERSION as usize] = [upgrade_v0];
+
+/// Version 0 is every payload written before versions were introduced
+///
+/// Buttons, `file_unique_id` and file metadata were added as optional keys,
+/// so these payloads are readable as is.
+fn upgrade_v0(_payload: &mut Map<String, JsonValue>) {}
+
+/// A payload converted to the current version
+pub struct UpgradedPayload {
+    pub value: JsonValue,
+    /// Version the payload was stored with
+    pub stored_version: u64,
+}
+
+/// Converts a stored payload to the current version
+pub fn upgrade(value: JsonValue) -> Result<UpgradedPayload, NoteSchemaError> {
+    let mut payload = match value {
+        JsonValue::Object(payload) => payload,
+        _ => return Err(NoteSchemaError::NotAnObject),
+    };
+    let stored_version = match payload.get(VERSION_KEY) {
+        Some(version) => version.as_u64().ok_or(NoteSchemaError::InvalidVersion)?,
+        None => 0,
+    };
+    if stored_version > CURRENT_VERSION {
+        return Err(NoteSchemaError::UnsupportedVersion(stored_version));
+    }
+    for step in &UPGRADES[stored_version as usize..] {
+        step(&mut payload);
+    }
+    payload.insert(String::from(VERSION_KEY), JsonValue::from(CURRENT_VERSION));
+    Ok(UpgradedPayload {
+        value: JsonValue::Object(payload),
+        stored_version,
+    })
+}
+
+#[derive(Debug)]
+pub enum NoteSchemaError {
+    InvalidVersion,
+    NotAnObject,
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for NoteSchemaError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::NoteSchemaError::*;
+        match self {
+            InvalidVersion => write!(out, "version must be a non-negative integer"),
+            NotAnObject => write!(out, "payload must be an object"),
+            UnsupportedVersion(version) => write!(
+                out,
+                "version {version} is newer than supported version {CURRENT_VERSION}"
+            ),
+        }
+    }
+}
+
+impl Error for NoteSchemaError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn upgrade_version() {
+        let upgraded = upgrade(json!({"Text": "text"})).unwrap();
+        assert_eq!(upgraded.stored_version, 0);
+        assert_eq!(upgraded.value, json!({"Text": "text", "version": CURRENT_VERSION}));
+
+        let upgraded = upgrade(json!({"Text": "text", "version": CURRENT_VERSION})).unwrap();
+        assert_eq!(upgraded.stored_version, CURRENT_VERSION);
+
+        assert!(matches!(
+            upgrade(json!({"Text": "text", "version": CURRENT_VERSION + 1})),
+            Err(NoteSchemaError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            upgrade(json!({"Text": "text", "version": "1"})),
+            Err(NoteSchemaError::InvalidVersion)
+        ));
+        assert!(matches!(upgrade(json!("text")), Err(NoteSchemaError::NotAnObject)));
+    }
+}
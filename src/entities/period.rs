@@ -0,0 +1,127 @@
+use std::{error::Error, fmt, str::FromStr};
+
+use time::{Duration, OffsetDateTime};
+
+/// A period of time written as a sequence of numbers with units, e.g. `7d` or `1h30m`
+///
+/// Supported units: `w` - weeks, `d` - days, `h` - hours, `m` - minutes, `s` - seconds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Period(Duration);
+
+impl Period {
+    pub fn as_duration(self) -> Duration {
+        self.0
+    }
+
+    /// Returns a time when the period ends, `None` when it is out of the supported range of dates
+    pub fn after(self, time: OffsetDateTime) -> Option<OffsetDateTime> {
+        time.checked_add(self.0)
+    }
+}
+
+impl FromStr for Period {
+    type Err = PeriodError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(PeriodError::Empty);
+        }
+        let mut result = Duration::ZERO;
+        let mut number = String::new();
+        for c in s.chars() {
+            if c.is_ascii_digit() {
+                number.push(c);
+                continue;
+            }
+            if number.is_empty() {
+                return Err(PeriodError::NoNumber);
+            }
+            let value: i64 = number.parse().map_err(|_| PeriodError::Overflow)?;
+            number.clear();
+            let unit = match c.to_ascii_lowercase() {
+                'w' => Duration::WEEK,
+                'd' => Duration::DAY,
+                'h' => Duration::HOUR,
+                'm' => Duration::MINUTE,
+                's' => Duration::SECOND,
+                c => return Err(PeriodError::UnknownUnit(c)),
+            };
+            result = unit
+                .checked_mul(i32::try_from(value).map_err(|_| PeriodError::Overflow)?)
+                .and_then(|x| result.checked_add(x))
+                .ok_or(PeriodError::Overflow)?;
+        }
+        if !number.is_empty() {
+            return Err(PeriodError::NoUnit);
+        }
+        if result.is_zero() {
+            return Err(PeriodError::Empty);
+        }
+        Ok(Self(result))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PeriodError {
+    Empty,
+    NoNumber,
+    NoUnit,
+    Overflow,
+    UnknownUnit(char),
+}
+
+impl fmt::Display for PeriodError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::PeriodError::*;
+        match self {
+            Empty => write!(out, "period is empty"),
+            NoNumber => write!(out, "unit must follow a number"),
+            NoUnit => write!(out, "number must be followed by a unit (w, d, h, m or s)"),
+            Overflow => write!(out, "period is too long"),
+            UnknownUnit(unit) => write!(out, "unknown unit: {unit}"),
+        }
+    }
+}
+
+impl Error for PeriodError {}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for (input, expected) in [
+            ("7d", Duration::days(7)),
+            ("1h30m", Duration::minutes(90)),
+            ("2w", Duration::weeks(2)),
+            ("45s", Duration::seconds(45)),
+            (" 1D12H ", Duration::hours(36)),
+        ] {
+            assert_eq!(input.parse::<Period>().unwrap().as_duration(), expected);
+        }
+        for (input, expected) in [
+            ("", PeriodError::Empty),
+            ("0d", PeriodError::Empty),
+            ("d", PeriodError::NoNumber),
+            ("7", PeriodError::NoUnit),
+            ("1h30", PeriodError::NoUnit),
+            ("7y", PeriodError::UnknownUnit('y')),
+            ("99999999999999999999d", PeriodError::Overflow),
+        ] {
+            assert_eq!(input.parse::<Period>().unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn after() {
+        let now = datetime!(2026-10-18 12:00 UTC);
+        let period: Period = "1d".parse().unwrap();
+        assert_eq!(period.after(now), Some(datetime!(2026-10-19 12:00 UTC)));
+        let period: Period = "999999w".parse().unwrap();
+        assert_eq!(period.after(now), None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{error::Error, fmt};
+
+use time::{
+    Date, Duration, OffsetDateTime, Time, UtcOffset,
+    macros::{format_description, time},
+};
+use tokio_postgres::Row;
+
+use crate::entities::Period;
+
+const DEFAULT_TIME: Time = time!(9:00);
+
+/// A note scheduled to be sent back to a chat
+#[derive(Clone, Debug)]
+pub struct Reminder {
+    id: i32,
+    chat_id: i64,
+    note_id: i32,
+    send_at: OffsetDateTime,
+    /// Number of failed attempts to send
+    attempts: i32,
+}
+
+impl Reminder {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn chat_id(&self) -> i64 {
+        self.chat_id
+    }
+
+    pub fn note_id(&self) -> i32 {
+        self.note_id
+    }
+
+    pub fn send_at(&self) -> OffsetDateTime {
+        self.send_at
+    }
+
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+}
+
+impl From<Row> for Reminder {
+    fn from(row: Row) -> Self {
+        Self {
+            id: row.get("id"),
+            chat_id: row.get("chat_id"),
+            note_id: row.get("note_id"),
+            send_at: row.get("send_at"),
+            attempts: row.get("attempts"),
+        }
+    }
+}
+
+/// Parses a time when a reminder should be sent
+///
+/// Supported formats (all times are in UTC):
+///
+/// * a period from now: `2h`, `1d12h` or `in 30m`;
+/// * a time of day: `9:00` (today or tomorrow, whichever comes first);
+/// * `today 18:30`, `tomorrow` or `tomorrow 9:00`;
+/// * a date with an optional time: `2026-10-20` or `2026-10-20 9:00`.
+///
+/// When a time of day is omitted, 9:00 is used.
+pub fn parse_reminder_time(input: &str, now: OffsetDateTime) -> Result<OffsetDateTime, ReminderTimeError> {
+    let now = now.to_offset(UtcOffset::UTC);
+    let input = input.trim().to_lowercase();
+    let mut parts = input.split_whitespace();
+    let first = parts.next().ok_or(ReminderTimeError::Invalid)?;
+    let second = parts.next();
+    if parts.next().is_some() {
+        return Err(ReminderTimeError::Invalid);
+    }
+    let result = match (first, second) {
+        ("in", Some(period)) => now
+            .checked_add(parse_period(period)?)
+            .ok_or(ReminderTimeError::Invalid)?,
+        ("today", time) => now.replace_time(parse_optional_time(time)?),
+        ("tomorrow", time) => now.replace_time(parse_optional_time(time)?) + Duration::DAY,
+        (value, None) if value.contains(':') => {
+            let result = now.replace_time(parse_time(value)?);
+            if result <= now { result + Duration::DAY } else { result }
+        }
+        (value, None) if !value.contains('-') => now
+            .checked_add(parse_period(value)?)
+            .ok_or(ReminderTimeError::Invalid)?,
+        (date, time) => {
+            let date = Date::parse(date, format_description!("[year]-[month]-[day]"))
+                .map_err(|_| ReminderTimeError::Invalid)?;
+            date.with_time(parse_optional_time(time)?).assume_utc()
+        }
+    };
+    if result <= now {
+        return Err(ReminderTimeError::Past);
+    }
+    Ok(result)
+}
+
+fn parse_period(value: &str) -> Result<Duration, ReminderTimeError> {
+    value
+        .parse::<Period>()
+        .map(Period::as_duration)
+        .map_err(|_| ReminderTimeError::Invalid)
+}
+
+fn parse_optional_time(value: Option<&str>) -> Result<Time, ReminderTimeError> {
+    value.map_or(Ok(DEFAULT_TIME), parse_time)
+}
+
+fn parse_time(value: &str) -> Result<Time, ReminderTimeError> {
+    let (hour, minute) = value.split_once(':').ok_or(ReminderTimeError::Invalid)?;
+    let hour = hour.parse().map_err(|_| ReminderTimeError::Invalid)?;
+    let minute = minute.parse().map_err(|_| ReminderTimeError::Invalid)?;
+    Time::from_hms(hour, minute, 0).map_err(|_| ReminderTimeError::Invalid)
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ReminderTimeError {
+    Invalid,
+    Past,
+}
+
+impl fmt::Display for ReminderTimeError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::ReminderTimeError::*;
+        match self {
+            Invalid => write!(
+                out,
+                "expected a period (2h, 1d12h), a time (9:00), tomorrow 9:00 or a date (2026-10-20 9:00)"
+            ),
+            Past => write!(out, "time is in the past"),
+        }
+    }
+}
+
+impl Error for ReminderTimeError {}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let now = datetime!(2026-10-18 12:00 UTC);
+        for (input, expected) in [
+            ("2h", datetime!(2026-10-18 14:00 UTC)),
+            ("in 1d12h", datetime!(2026-10-20 00:00 UTC)),
+            ("18:30", datetime!(2026-10-18 18:30 UTC)),
+            ("9:00", datetime!(2026-10-19 09:00 UTC)),
+            ("today 13:05", datetime!(2026-10-18 13:05 UTC)),
+            ("tomorrow", datetime!(2026-10-19 09:00 UTC)),
+            ("Tomorrow 7:15", datetime!(2026-10-19 07:15 UTC)),
+            ("2026-10-20", datetime!(2026-10-20 09:00 UTC)),
+            ("2026-10-20 23:59", datetime!(2026-10-20 23:59 UTC)),
+        ] {
+            assert_eq!(parse_reminder_time(input, now), Ok(expected), "{input}");
+        }
+        for (input, expected) in [
+            ("", ReminderTimeError::Invalid),
+            ("soon", ReminderTimeError::Invalid),
+            ("25:00", ReminderTimeError::Invalid),
+            ("tomorrow 9", ReminderTimeError::Invalid),
+            ("2026-13-01", ReminderTimeError::Invalid),
+            ("tomorrow 9:00 please", ReminderTimeError::Invalid),
+            ("999999w", ReminderTimeError::Invalid),
+            ("in 999999w", ReminderTimeError::Invalid),
+            ("today 11:00", ReminderTimeError::Past),
+            ("2026-10-01", ReminderTimeError::Past),
+        ] {
+            assert_eq!(parse_reminder_time(input, now), Err(expected), "{input}");
+        }
+    }
+}
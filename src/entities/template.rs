@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+/// A text with placeholders filled from inline query arguments
+///
+/// Supported placeholders:
+///
+/// * `{1}`, `{2}`, ... - an argument at the given position (starting from 1);
+/// * `{name}` - a value of `name=value` argument,
+///   or the next positional argument which is not referenced by number.
+///
+/// Use `{{` and `}}` to insert braces as is.
+/// Placeholders without a value are kept unchanged.
+pub struct Template<'a> {
+    text: &'a str,
+}
+
+impl<'a> Template<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text }
+    }
+
+    pub fn has_placeholders(&self) -> bool {
+        self.parse().any(|x| matches!(x, Token::Placeholder(_)))
+    }
+
+    pub fn render<S: AsRef<str>>(&self, args: &[S]) -> String {
+        let mut positional = Vec::new();
+        let mut named = HashMap::new();
+        for arg in args {
+            let arg = arg.as_ref();
+            match arg.split_once('=') {
+                Some((name, value)) if !name.is_empty() => {
+                    named.insert(name, value);
+                }
+                _ => positional.push(arg),
+            }
+        }
+        let mut referenced = vec![false; positional.len()];
+        for token in self.parse() {
+            if let Token::Placeholder(name) = token
+                && let Some(idx) = position(name).flatten().filter(|idx| *idx < positional.len())
+            {
+                referenced[idx] = true;
+            }
+        }
+        let mut unreferenced = positional
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| !referenced[*idx])
+            .map(|(_, value)| *value);
+
+        let mut result = String::with_capacity(self.text.len());
+        for token in self.parse() {
+            match token {
+                Token::Text(text) => result.push_str(text),
+                Token::Placeholder(name) => {
+                    let value = match position(name) {
+                        Some(idx) => idx.and_then(|idx| positional.get(idx).copied()),
+                        None => match named.get(name) {
+                            Some(value) => Some(*value),
+                            None => {
+                                let value = unreferenced.next();
+                                if let Some(value) = value {
+                                    named.insert(name, value);
+                                }
+                                value
+                            }
+                        },
+                    };
+                    match value {
+                        Some(value) => result.push_str(value),
+                        None => {
+                            result.push('{');
+                            result.push_str(name);
+                            result.push('}');
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn parse(&self) -> impl Iterator<Item = Token<'a>> {
+        let mut rest = self.text;
+        std::iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                let token = Token::Text(&rest[..1]);
+                rest = &rest[2..];
+                return Some(token);
+            }
+            if let Some(tail) = rest.strip_prefix('{')
+                && let Some((name, tail)) = tail.split_once('}')
+                && is_placeholder_name(name)
+            {
+                rest = tail;
+                return Some(Token::Placeholder(name));
+            }
+            let start = first.len_utf8();
+            let end = rest[start..].find(['{', '}']).map_or(rest.len(), |x| x + start);
+            let token = Token::Text(&rest[..end]);
+            rest = &rest[end..];
+            Some(token)
+        })
+    }
+}
+
+enum Token<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Returns `None` when a placeholder is named and `Some(None)` when its position is out of range
+fn position(name: &str) -> Option<Option<usize>> {
+    if name.bytes().all(|x| x.is_ascii_digit()) {
+        Some(name.parse::<usize>().ok().and_then(|x| x.checked_sub(1)))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn has_placeholders() {
+        assert!(Template::new("Hello, {name}!").has_placeholders());
+        assert!(Template::new("{1}").has_placeholders());
+        assert!(!Template::new("Hello, world!").has_placeholders());
+        assert!(!Template::new("{{name}} {} { name }").has_placeholders());
+    }
+
+    #[test]
+    fn render_named() {
+        let template = Template::new("Hello, {name}! Welcome to {team}, {name}.");
+        assert_eq!(
+            template.render(&["Alice", "SRE"]),
+            "Hello, Alice! Welcome to SRE, Alice."
+        );
+        assert_eq!(
+            template.render(&["team=SRE", "Bob"]),
+            "Hello, Bob! Welcome to SRE, Bob."
+        );
+        assert_eq!(template.render(&["Alice"]), "Hello, Alice! Welcome to {team}, Alice.");
+        assert_eq!(
+            template.render::<&str>(&[]),
+            "Hello, {name}! Welcome to {team}, {name}."
+        );
+    }
+
+    #[test]
+    fn render_positional() {
+        let template = Template::new("{2} {1} {3} {0}");
+        assert_eq!(template.render(&["a", "b"]), "b a {3} {0}");
+        let template = Template::new("Incident {1}: {status}");
+        assert_eq!(template.render(&["42", "resolved"]), "Incident 42: resolved");
+    }
+
+    #[test]
+    fn render_escaped() {
+        let template = Template::new("{{name}} is {name} {} {x y} }{");
+        assert_eq!(template.render(&["Alice"]), "{name} is Alice {} {x y} }{");
+    }
+
+    #[test]
+    fn render_unicode() {
+        let template = Template::new("Привет, {имя}!");
+        assert_eq!(template.render(&["Алиса"]), "Привет, Алиса!");
+    }
+}
@@ -1,29 +1,78 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, time::Duration};
 
 use carapax::{
     Ref,
     api::{Client, ExecuteError},
     dialogue::{DialogueInput, DialogueResult, DialogueState},
+    session::{Session, SessionError},
     types::{ChatPeerId, Message, SendMessage},
 };
 use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 
 use crate::{
-    entities::{Keywords, NoteData},
+    config::SessionOptions,
+    entities::{Keywords, NewNote, NoteButton, NoteData, Period},
     services::{NotesService, NotesServiceError},
-    session::SessionBackend,
+    session::{self, SessionBackend},
 };
 
+const CANCEL_COMMAND: &str = "/cancel";
+const MAX_TITLE_LEN: usize = 255;
+const MERGE_COMMAND: &str = "/merge";
+const NEW_COMMAND: &str = "/new";
+const SKIP_COMMAND: &str = "/skip";
+
 pub async fn handle(
     client: Ref<Client>,
     notes_service: Ref<NotesService>,
+    session_options: Ref<SessionOptions>,
     chat_id: ChatPeerId,
     input: DialogueInput<AddState, SessionBackend>,
     message: Message,
+    mut session: Session<SessionBackend>,
 ) -> Result<DialogueResult<AddState>, AddError> {
-    Ok(match input.state {
+    let timeout = Duration::from_secs(session_options.dialogue_timeout);
+    // The collector cancels idle dialogues periodically, a message may arrive before it runs
+    if session::is_dialogue_idle(&mut session, timeout)
+        .await
+        .map_err(AddError::Session)?
+    {
+        session::clear_dialogue::<AddState>(&mut session)
+            .await
+            .map_err(AddError::Session)?;
+        client
+            .execute(SendMessage::new(
+                chat_id,
+                session::dialogue_timeout_message::<AddState>(timeout),
+            ))
+            .await?;
+        return Ok(DialogueResult::Exit);
+    }
+    let result = handle_state(&client, &notes_service, chat_id, input.state, message).await?;
+    match result {
+        DialogueResult::Next(_) => session::touch_dialogue(&mut session).await,
+        DialogueResult::Exit => session::clear_dialogue::<AddState>(&mut session).await,
+    }
+    .map_err(AddError::Session)?;
+    Ok(result)
+}
+
+async fn handle_state(
+    client: &Client,
+    notes_service: &NotesService,
+    chat_id: ChatPeerId,
+    state: AddState,
+    message: Message,
+) -> Result<DialogueResult<AddState>, AddError> {
+    Ok(match state {
         AddState::Start => {
-            client.execute(SendMessage::new(chat_id, "Send any message")).await?;
+            client
+                .execute(SendMessage::new(
+                    chat_id,
+                    format!("Send any message, {CANCEL_COMMAND} to stop adding a note"),
+                ))
+                .await?;
             AddState::SetMessage
         }
         AddState::SetMessage => {
@@ -41,12 +90,208 @@ pub async fn handle(
             let keywords = match message.get_text() {
                 Some(text) => Keywords::from(text.data.split(' ')),
                 None => {
-                    client.execute(SendMessage::new(chat_id, "Done")).await?;
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Send keywords as text separated by spaces or {CANCEL_COMMAND}"),
+                        ))
+                        .await?;
                     return Ok(AddState::SetKeywords(note_data).into());
                 }
             };
+            let duplicate = notes_service
+                .find_duplicate(&note_data)
+                .await
+                .map_err(AddError::FindDuplicate)?;
+            let note = note_data.into_new(keywords);
+            match duplicate {
+                Some(duplicate) => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!(
+                                "Note {} has the same content, its keywords: {}\n\n\
+                                Send {MERGE_COMMAND} to add new keywords to it or {NEW_COMMAND} to create a copy",
+                                duplicate.id(),
+                                duplicate.keywords().as_string()
+                            ),
+                        ))
+                        .await?;
+                    AddState::ResolveDuplicate {
+                        note_id: duplicate.id(),
+                        note,
+                    }
+                }
+                None => {
+                    client
+                        .execute(SendMessage::new(chat_id, format!("Send title or {SKIP_COMMAND}")))
+                        .await?;
+                    AddState::SetTitle(note)
+                }
+            }
+        }
+        AddState::ResolveDuplicate { note_id, note } => {
+            match message.get_text().map(|text| text.data.as_str()) {
+                Some(MERGE_COMMAND) => {
+                    if let Some(duplicate) = notes_service.get(note_id).await.map_err(AddError::GetNote)? {
+                        let mut keywords = duplicate.keywords().clone();
+                        keywords.merge(note.keywords());
+                        notes_service
+                            .update(&duplicate.with_keywords(keywords.clone()))
+                            .await
+                            .map_err(AddError::SetKeywords)?;
+                        client
+                            .execute(SendMessage::new(
+                                chat_id,
+                                format!("Keywords of note {note_id}: {}", keywords.as_string()),
+                            ))
+                            .await?;
+                        return Ok(DialogueResult::Exit);
+                    }
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Note {note_id} is not found, a new note will be created\n\nSend title or {SKIP_COMMAND}"),
+                        ))
+                        .await?;
+                    AddState::SetTitle(note)
+                }
+                Some(NEW_COMMAND) => {
+                    client
+                        .execute(SendMessage::new(chat_id, format!("Send title or {SKIP_COMMAND}")))
+                        .await?;
+                    AddState::SetTitle(note)
+                }
+                _ => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Send {MERGE_COMMAND} or {NEW_COMMAND}"),
+                        ))
+                        .await?;
+                    AddState::ResolveDuplicate { note_id, note }
+                }
+            }
+        }
+        AddState::SetTitle(note) => {
+            let title = match message.get_text() {
+                Some(text) if text.data == SKIP_COMMAND => None,
+                Some(text) if text.data.chars().count() <= MAX_TITLE_LEN => Some(text.data.clone()),
+                Some(_) => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Title must not exceed {MAX_TITLE_LEN} characters"),
+                        ))
+                        .await?;
+                    return Ok(AddState::SetTitle(note).into());
+                }
+                None => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Send title as text or {SKIP_COMMAND}"),
+                        ))
+                        .await?;
+                    return Ok(AddState::SetTitle(note).into());
+                }
+            };
+            client
+                .execute(SendMessage::new(chat_id, format!("Send description or {SKIP_COMMAND}")))
+                .await?;
+            AddState::SetDescription(note.with_title(title))
+        }
+        AddState::SetDescription(note) => {
+            let description = match message.get_text() {
+                Some(text) if text.data == SKIP_COMMAND => None,
+                Some(text) => Some(text.data.clone()),
+                None => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Send description as text or {SKIP_COMMAND}"),
+                        ))
+                        .await?;
+                    return Ok(AddState::SetDescription(note).into());
+                }
+            };
+            client
+                .execute(SendMessage::new(
+                    chat_id,
+                    format!(
+                        "Send buttons, one per line, or {SKIP_COMMAND}\n\n\
+                        Open runbook | https://example.com/runbook\n\
+                        Share | inline: query"
+                    ),
+                ))
+                .await?;
+            AddState::SetButtons(note.with_description(description))
+        }
+        AddState::SetButtons(note) => {
+            let buttons = match message.get_text() {
+                Some(text) if text.data == SKIP_COMMAND => Vec::new(),
+                Some(text) => match NoteButton::parse_list(&text.data) {
+                    Ok(buttons) => buttons,
+                    Err(err) => {
+                        client
+                            .execute(SendMessage::new(chat_id, format!("Could not parse buttons: {err}")))
+                            .await?;
+                        return Ok(AddState::SetButtons(note).into());
+                    }
+                },
+                None => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Send buttons as text or {SKIP_COMMAND}"),
+                        ))
+                        .await?;
+                    return Ok(AddState::SetButtons(note).into());
+                }
+            };
+            client
+                .execute(SendMessage::new(
+                    chat_id,
+                    format!("Send expiration period (e.g. 7d, 12h or 1h30m) or {SKIP_COMMAND}"),
+                ))
+                .await?;
+            AddState::SetExpiration(note.with_buttons(buttons))
+        }
+        AddState::SetExpiration(note) => {
+            let expires_at = match message.get_text() {
+                Some(text) if text.data == SKIP_COMMAND => None,
+                Some(text) => match text.data.parse::<Period>() {
+                    Ok(period) => match period.after(OffsetDateTime::now_utc()) {
+                        Some(expires_at) => Some(expires_at),
+                        None => {
+                            client
+                                .execute(SendMessage::new(
+                                    chat_id,
+                                    format!("Expiry is too far in the future, send a shorter period or {SKIP_COMMAND}"),
+                                ))
+                                .await?;
+                            return Ok(AddState::SetExpiration(note).into());
+                        }
+                    },
+                    Err(err) => {
+                        client
+                            .execute(SendMessage::new(chat_id, format!("Could not parse period: {err}")))
+                            .await?;
+                        return Ok(AddState::SetExpiration(note).into());
+                    }
+                },
+                None => {
+                    client
+                        .execute(SendMessage::new(
+                            chat_id,
+                            format!("Send expiration period as text or {SKIP_COMMAND}"),
+                        ))
+                        .await?;
+                    return Ok(AddState::SetExpiration(note).into());
+                }
+            };
             notes_service
-                .create(note_data.into_new(keywords))
+                .create(note.with_expires_at(expires_at))
                 .await
                 .map_err(AddError::CreateNote)?;
             client.execute(SendMessage::new(chat_id, "Done")).await?;
@@ -62,6 +307,14 @@ pub enum AddState {
     Start,
     SetMessage,
     SetKeywords(NoteData),
+    ResolveDuplicate {
+        note_id: i32,
+        note: NewNote,
+    },
+    SetTitle(NewNote),
+    SetDescription(NewNote),
+    SetButtons(NewNote),
+    SetExpiration(NewNote),
 }
 
 impl DialogueState for AddState {
@@ -74,6 +327,10 @@ impl DialogueState for AddState {
 pub enum AddError {
     Execute(ExecuteError),
     CreateNote(NotesServiceError),
+    FindDuplicate(NotesServiceError),
+    GetNote(NotesServiceError),
+    Session(SessionError),
+    SetKeywords(NotesServiceError),
 }
 
 impl From<ExecuteError> for AddError {
@@ -88,6 +345,10 @@ impl fmt::Display for AddError {
         match self {
             Execute(err) => err.fmt(out),
             CreateNote(err) => err.fmt(out),
+            FindDuplicate(err) => err.fmt(out),
+            GetNote(err) => err.fmt(out),
+            Session(err) => err.fmt(out),
+            SetKeywords(err) => err.fmt(out),
         }
     }
 }
@@ -98,6 +359,10 @@ impl Error for AddError {
         Some(match self {
             Execute(err) => err,
             CreateNote(err) => err,
+            FindDuplicate(err) => err,
+            GetNote(err) => err,
+            Session(err) => err,
+            SetKeywords(err) => err,
         })
     }
 }
@@ -0,0 +1,66 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    dialogue::DialogueState,
+    session::{Session, SessionError},
+    types::{ChatPeerId, SendMessage},
+};
+
+use crate::{
+    handlers::AddState,
+    session::{self, SessionBackend},
+};
+
+pub async fn handle(
+    client: Ref<Client>,
+    chat_id: ChatPeerId,
+    mut session: Session<SessionBackend>,
+) -> Result<(), CancelError> {
+    let state: Option<AddState> = session
+        .get(AddState::session_key())
+        .await
+        .map_err(CancelError::Session)?;
+    session::clear_dialogue::<AddState>(&mut session)
+        .await
+        .map_err(CancelError::Session)?;
+    let text = match state {
+        Some(_) => format!("/{} is cancelled", AddState::dialogue_name()),
+        None => String::from("Nothing to cancel"),
+    };
+    client.execute(SendMessage::new(chat_id, text)).await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum CancelError {
+    Execute(ExecuteError),
+    Session(SessionError),
+}
+
+impl From<ExecuteError> for CancelError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for CancelError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::CancelError::*;
+        match self {
+            Execute(err) => err.fmt(out),
+            Session(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for CancelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::CancelError::*;
+        Some(match self {
+            Execute(err) => err,
+            Session(err) => err,
+        })
+    }
+}
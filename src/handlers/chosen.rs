@@ -0,0 +1,10 @@
+use carapax::{Ref, types::ChosenInlineResult};
+
+use crate::services::{NotesService, NotesServiceError};
+
+pub async fn handle(notes_service: Ref<NotesService>, input: ChosenInlineResult) -> Result<(), NotesServiceError> {
+    if let Ok(note_id) = input.result_id.parse() {
+        notes_service.mark_used(note_id).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    types::{ChatPeerId, Command, SendMessage},
+};
+use time::OffsetDateTime;
+
+use super::format_datetime;
+use crate::{
+    entities::DigestSettings,
+    services::{DigestsService, DigestsServiceError},
+};
+
+const OFF: &str = "off";
+const USAGE: &str = "Usage: /digest <daily|weekly> [random|forgotten] [size] or /digest off";
+
+pub async fn handle(
+    client: Ref<Client>,
+    digests_service: Ref<DigestsService>,
+    command: Command,
+    chat_id: ChatPeerId,
+) -> Result<(), DigestError> {
+    let args = command.get_args();
+    let text = match args {
+        [] => match digests_service.get(chat_id.into()).await.map_err(DigestError::Get)? {
+            Some(digest) => format!(
+                "Digest: {}\nNext: {}",
+                digest.settings(),
+                format_datetime(digest.next_at())
+            ),
+            None => format!("Digest is off\n\n{USAGE}"),
+        },
+        [value] if value.eq_ignore_ascii_case(OFF) => {
+            if digests_service
+                .remove(chat_id.into())
+                .await
+                .map_err(DigestError::Remove)?
+            {
+                String::from("Digest is turned off")
+            } else {
+                String::from("Digest is already off")
+            }
+        }
+        args => match DigestSettings::parse(args) {
+            Ok(settings) => {
+                let next_at = OffsetDateTime::now_utc() + settings.schedule.as_duration();
+                digests_service
+                    .set(chat_id.into(), settings, next_at)
+                    .await
+                    .map_err(DigestError::Set)?;
+                format!("Digest: {settings}\nNext: {}", format_datetime(next_at))
+            }
+            Err(err) => format!("Could not parse digest settings: {err}\n\n{USAGE}"),
+        },
+    };
+    client.execute(SendMessage::new(chat_id, text)).await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum DigestError {
+    Execute(ExecuteError),
+    Get(DigestsServiceError),
+    Remove(DigestsServiceError),
+    Set(DigestsServiceError),
+}
+
+impl From<ExecuteError> for DigestError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for DigestError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::DigestError::*;
+        match self {
+            Execute(err) => err.fmt(out),
+            Get(err) => err.fmt(out),
+            Remove(err) => err.fmt(out),
+            Set(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for DigestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::DigestError::*;
+        Some(match self {
+            Execute(err) => err,
+            Get(err) => err,
+            Remove(err) => err,
+            Set(err) => err,
+        })
+    }
+}
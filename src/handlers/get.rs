@@ -0,0 +1,102 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    types::{ChatPeerId, Command, ReplyParameters, SendMessage},
+};
+
+use super::format_datetime;
+use crate::{
+    entities::{Note, NoteButton, NoteData},
+    services::{DeliveryService, NotesService, NotesServiceError},
+};
+
+pub async fn handle(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    delivery_service: Ref<DeliveryService>,
+    command: Command,
+    chat_id: ChatPeerId,
+) -> Result<(), GetError> {
+    let text = match command.get_args().first().map(|value| value.parse()).transpose() {
+        Ok(Some(note_id)) => match notes_service.get(note_id).await.map_err(GetError::GetNote)? {
+            Some(note) => {
+                let message = delivery_service.send(chat_id, note.data()).await?;
+                notes_service.mark_used(note_id).await.map_err(GetError::MarkUsed)?;
+                let mut method = SendMessage::new(chat_id, format_metadata(&note))
+                    .with_reply_parameters(ReplyParameters::new(message.id));
+                if let Some(markup) = NoteButton::to_markup(note.buttons()) {
+                    method = method.with_reply_markup(markup);
+                }
+                client.execute(method).await?;
+                return Ok(());
+            }
+            None => "Not found",
+        },
+        Ok(None) => "Note ID is required",
+        Err(_) => "Note ID is not an integer",
+    };
+    client.execute(SendMessage::new(chat_id, text)).await?;
+    Ok(())
+}
+
+fn format_metadata(note: &Note) -> String {
+    let mut result = format!(
+        "ID: {}\nType: {}\nKeywords: {}",
+        note.id(),
+        note.data().kind(),
+        note.keywords().as_string()
+    );
+    if let Some(title) = note.title() {
+        result.push_str(&format!("\nTitle: {title}"));
+    }
+    if let Some(description) = note.description() {
+        result.push_str(&format!("\nDescription: {description}"));
+    }
+    if let Some(file) = note.data().file().and_then(|file| file.summary()) {
+        result.push_str(&format!("\nFile: {file}"));
+    }
+    if let Some(expires_at) = note.expires_at() {
+        result.push_str(&format!("\nExpires: {}", format_datetime(expires_at)));
+    }
+    if let NoteData::Location { latitude, longitude } = note.data() {
+        result.push_str(&format!("\nCoordinates: {latitude}, {longitude}"));
+    }
+    result
+}
+
+#[derive(Debug)]
+pub enum GetError {
+    Execute(ExecuteError),
+    GetNote(NotesServiceError),
+    MarkUsed(NotesServiceError),
+}
+
+impl From<ExecuteError> for GetError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for GetError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::GetError::*;
+        match self {
+            Execute(err) => err.fmt(out),
+            GetNote(err) => err.fmt(out),
+            MarkUsed(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for GetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::GetError::*;
+        Some(match self {
+            Execute(err) => err,
+            GetNote(err) => err,
+            MarkUsed(err) => err,
+        })
+    }
+}
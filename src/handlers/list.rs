@@ -3,30 +3,200 @@ use std::{error::Error, fmt};
 use carapax::{
     Ref,
     api::{Client, ExecuteError},
-    types::{Message, ParseMode, SendMessage},
+    types::{
+        AnswerCallbackQuery, CallbackQuery, ChatPeerId, EditMessageText, InlineKeyboardButton, InlineKeyboardError,
+        InlineKeyboardMarkup, MaybeInaccessibleMessage, Message, SendMessage,
+    },
 };
+use serde::{Deserialize, Serialize};
 
-use crate::services::{NotesService, NotesServiceError};
+use crate::{
+    entities::{LIST_PAGE_SIZE, NoteKind, NoteOrder},
+    render::RenderMode,
+    services::{NoteList, NotesService, NotesServiceError},
+};
 
-pub async fn handle(client: Ref<Client>, notes_service: Ref<NotesService>, message: Message) -> Result<(), ListError> {
+pub async fn handle(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    mode: Ref<RenderMode>,
+    message: Message,
+) -> Result<(), ListError> {
     let chat_id = message.chat.get_id();
-    let items: Vec<String> = notes_service.get_list().await.map_err(ListError::GetNotes)?.collect();
-    if items.is_empty() {
-        client.execute(SendMessage::new(chat_id, "There are no items")).await?;
-    } else {
-        for item in items {
+    let page = ListPage::load(&notes_service, *mode, ListQuery::default()).await?;
+    let mut method = SendMessage::new(chat_id, page.text).with_parse_mode(mode.parse_mode());
+    if let Some(markup) = page.markup {
+        method = method.with_reply_markup(markup);
+    }
+    client.execute(method).await?;
+    Ok(())
+}
+
+pub async fn is_callback(query: CallbackQuery) -> bool {
+    matches!(query.parse_data::<ListCallback>(), Ok(Some(_)))
+}
+
+pub async fn handle_callback(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    mode: Ref<RenderMode>,
+    query: CallbackQuery,
+) -> Result<(), ListError> {
+    let (chat_id, message_id) = match query.message {
+        Some(MaybeInaccessibleMessage::Message(ref message)) => (message.chat.get_id(), message.id),
+        Some(MaybeInaccessibleMessage::InaccessibleMessage(ref message)) => (message.chat.get_id(), message.message_id),
+        None => {
             client
-                .execute(SendMessage::new(chat_id, item).with_parse_mode(ParseMode::MarkdownV2))
+                .execute(AnswerCallbackQuery::new(query.id).with_text("Message is not available"))
                 .await?;
+            return Ok(());
         }
+    };
+    if let Ok(Some(ListCallback::Show(list_query))) = query.parse_data::<ListCallback>() {
+        let page = ListPage::load(&notes_service, *mode, list_query).await?;
+        edit_page(&client, chat_id, message_id, *mode, page).await?;
     }
+    client.execute(AnswerCallbackQuery::new(query.id)).await?;
     Ok(())
 }
 
+async fn edit_page(
+    client: &Client,
+    chat_id: ChatPeerId,
+    message_id: i64,
+    mode: RenderMode,
+    page: ListPage,
+) -> Result<(), ListError> {
+    let mut method =
+        EditMessageText::for_chat_message(chat_id, message_id, page.text).with_parse_mode(mode.parse_mode());
+    if let Some(markup) = page.markup {
+        method = method.with_reply_markup(markup);
+    }
+    client.execute(method).await?;
+    Ok(())
+}
+
+struct ListPage {
+    text: String,
+    markup: Option<InlineKeyboardMarkup>,
+}
+
+impl ListPage {
+    async fn load(notes_service: &NotesService, mode: RenderMode, query: ListQuery) -> Result<Self, ListError> {
+        let mut number = query.page;
+        let mut list = load_list(notes_service, query, number).await?;
+        if list.total > 0 && number as i64 * LIST_PAGE_SIZE >= list.total {
+            // The page may be gone after its last notes were deleted
+            number = ((list.total - 1) / LIST_PAGE_SIZE) as usize;
+            list = load_list(notes_service, query, number).await?;
+        }
+        let total_pages = ((list.total + LIST_PAGE_SIZE - 1) / LIST_PAGE_SIZE) as usize;
+        let text = if !list.items.is_empty() {
+            list.items.with_mode(mode).render()
+        } else if query.kind.is_none() {
+            return Ok(Self {
+                text: String::from("There are no items"),
+                markup: None,
+            });
+        } else {
+            String::from("There are no items of this type")
+        };
+        let query = ListQuery { page: number, ..query };
+
+        let kind_label = match query.kind {
+            Some(kind) => format!("Type: {kind}"),
+            None => String::from("Type: all"),
+        };
+        let next_kind = match query.kind {
+            None => NoteKind::ALL.first().copied(),
+            Some(kind) => NoteKind::ALL
+                .iter()
+                .position(|x| *x == kind)
+                .and_then(|idx| NoteKind::ALL.get(idx + 1).copied()),
+        };
+        let mut markup = InlineKeyboardMarkup::default().add_row([
+            ListCallback::Show(ListQuery {
+                page: 0,
+                kind: next_kind,
+                ..query
+            })
+            .button(kind_label)?,
+            ListCallback::Show(ListQuery {
+                page: 0,
+                order: query.order.next(),
+                ..query
+            })
+            .button(format!("Sort: {}", query.order))?,
+        ]);
+        if total_pages > 1 {
+            let mut navigation = Vec::new();
+            if number > 0 {
+                navigation.push(
+                    ListCallback::Show(ListQuery {
+                        page: number - 1,
+                        ..query
+                    })
+                    .button("« Previous")?,
+                );
+            }
+            navigation.push(ListCallback::Current.button(format!("{} / {}", number + 1, total_pages))?);
+            if number + 1 < total_pages {
+                navigation.push(
+                    ListCallback::Show(ListQuery {
+                        page: number + 1,
+                        ..query
+                    })
+                    .button("Next »")?,
+                );
+            }
+            markup = markup.add_row(navigation);
+        }
+        Ok(Self {
+            text,
+            markup: Some(markup),
+        })
+    }
+}
+
+async fn load_list(notes_service: &NotesService, query: ListQuery, number: usize) -> Result<NoteList, ListError> {
+    notes_service
+        .get_list(query.kind, query.order, number as i64 * LIST_PAGE_SIZE, LIST_PAGE_SIZE)
+        .await
+        .map_err(ListError::GetNotes)
+}
+
+#[derive(Clone, Copy, Default, Deserialize, Serialize)]
+struct ListQuery {
+    #[serde(rename = "p")]
+    page: usize,
+    #[serde(rename = "k")]
+    kind: Option<NoteKind>,
+    #[serde(rename = "o")]
+    order: NoteOrder,
+}
+
+#[derive(Deserialize, Serialize)]
+enum ListCallback {
+    #[serde(rename = "list")]
+    Show(ListQuery),
+    #[serde(rename = "list_current")]
+    Current,
+}
+
+impl ListCallback {
+    fn button<T>(&self, text: T) -> Result<InlineKeyboardButton, ListError>
+    where
+        T: Into<String>,
+    {
+        InlineKeyboardButton::for_callback_data_struct(text, self).map_err(ListError::Keyboard)
+    }
+}
+
 #[derive(Debug)]
 pub enum ListError {
     Execute(ExecuteError),
     GetNotes(NotesServiceError),
+    Keyboard(InlineKeyboardError),
 }
 
 impl From<ExecuteError> for ListError {
@@ -41,6 +211,7 @@ impl fmt::Display for ListError {
         match self {
             Execute(err) => err.fmt(out),
             GetNotes(err) => err.fmt(out),
+            Keyboard(err) => err.fmt(out),
         }
     }
 }
@@ -51,6 +222,7 @@ impl Error for ListError {
         Some(match self {
             Execute(err) => err,
             GetNotes(err) => err,
+            Keyboard(err) => err,
         })
     }
 }
@@ -1,16 +1,46 @@
-use carapax::{Chain, CommandExt, CommandPredicate, dialogue::DialogueExt};
+use carapax::{Chain, CommandExt, CommandPredicate, PredicateExt, dialogue::DialogueExt};
+use time::{OffsetDateTime, UtcOffset, macros::format_description};
 
 use crate::session::SessionBackend;
 
+pub use self::add::AddState;
+
 mod add;
+mod cancel;
+mod chosen;
+mod digest;
+mod get;
 mod list;
 mod query;
+mod random;
+mod remind;
+mod reminders;
 mod remove;
+mod search;
 
 pub fn setup() -> Chain {
     Chain::once()
         .with(query::handle)
+        .with(chosen::handle)
+        .with(list::handle_callback.with_predicate(list::is_callback))
+        .with(search::handle_callback.with_predicate(search::is_callback))
+        .with(reminders::handle_callback.with_predicate(reminders::is_callback))
+        // Must be reached before the dialogue, which would otherwise swallow the update
+        .with(cancel::handle.with_command("/cancel"))
+        .with(digest::handle.with_command("/digest"))
+        .with(get::handle.with_command("/get"))
         .with(list::handle.with_command("/list"))
+        .with(random::handle.with_command("/random"))
+        .with(remind::handle.with_command("/remind"))
+        .with(reminders::handle.with_command("/reminders"))
         .with(remove::handle.with_command("/remove"))
+        .with(search::handle.with_command("/search"))
         .with(add::handle.with_dialogue::<SessionBackend>(CommandPredicate::new("/add")))
 }
+
+fn format_datetime(value: OffsetDateTime) -> String {
+    value
+        .to_offset(UtcOffset::UTC)
+        .format(format_description!("[year]-[month]-[day] [hour]:[minute] UTC"))
+        .unwrap_or_else(|_| value.to_string())
+}
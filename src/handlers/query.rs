@@ -1,4 +1,4 @@
-use std::{error::Error, fmt};
+use std::{cmp::Reverse, error::Error, fmt};
 
 use carapax::{
     Ref,
@@ -7,18 +7,78 @@ use carapax::{
 };
 
 use crate::{
-    entities::Keywords,
+    config::Thumbnails,
+    entities::{Keywords, Note},
     services::{NotesService, NotesServiceError},
 };
 
+/// Maximum number of results allowed in an answer to an inline query
+const MAX_RESULTS: usize = 50;
+const RANDOM_PREFIX: &str = "!random";
+
 pub async fn handle(
     client: Ref<Client>,
     notes_service: Ref<NotesService>,
+    thumbnails: Ref<Thumbnails>,
     input: InlineQuery,
 ) -> Result<(), QueryError> {
-    let keywords = Keywords::from(input.query.split(' '));
-    let notes = notes_service.query(keywords).await.map_err(QueryError::QueryNotes)?;
-    let results: Vec<InlineQueryResult> = notes.into_iter().map(Into::into).collect();
+    if let Some(keywords) = input.query.strip_prefix(RANDOM_PREFIX)
+        && (keywords.is_empty() || keywords.starts_with(' '))
+    {
+        let keywords = Keywords::from(keywords.split_whitespace());
+        let results: Vec<InlineQueryResult> = notes_service
+            .random(&keywords)
+            .await
+            .map_err(QueryError::QueryNotes)?
+            .into_iter()
+            .map(|note| note.into_inline_query_result(&thumbnails))
+            .collect();
+        // A new note must be picked on every request
+        client
+            .execute(AnswerInlineQuery::new(input.id, results).with_cache_time(0))
+            .await?;
+        return Ok(());
+    }
+
+    let words: Vec<&str> = input.query.split_whitespace().collect();
+    if words.is_empty() {
+        client.execute(AnswerInlineQuery::new(input.id, Vec::new())).await?;
+        return Ok(());
+    }
+    let mut notes = notes_service
+        .query(Keywords::from(words.iter().copied()))
+        .await
+        .map_err(QueryError::QueryNotes)?;
+
+    // Leading words are keywords of a template and trailing words are its arguments,
+    // every split includes the first word, so all candidates are fetched at once.
+    // Longer lists of keywords are more specific, so they go first.
+    if words.len() > 1 {
+        let templates = notes_service
+            .query_templates(words[0])
+            .await
+            .map_err(QueryError::QueryNotes)?;
+        let mut matches: Vec<(usize, Note)> = templates
+            .into_iter()
+            .filter(|template| notes.iter().all(|note| note.id() != template.id()))
+            .filter_map(|template| {
+                (1..words.len())
+                    .rev()
+                    .find(|split_at| template.keywords().contains_all(&words[..*split_at]))
+                    .map(|split_at| (split_at, template))
+            })
+            .collect();
+        matches.sort_by_key(|(split_at, _)| Reverse(*split_at));
+        for (split_at, template) in matches {
+            notes.push(template.with_arguments(&words[split_at..]));
+        }
+    }
+
+    let results: Vec<InlineQueryResult> = notes
+        .into_iter()
+        .take(MAX_RESULTS)
+        .map(|note| note.into_inline_query_result(&thumbnails))
+        .collect();
     client.execute(AnswerInlineQuery::new(input.id, results)).await?;
     Ok(())
 }
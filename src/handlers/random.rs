@@ -0,0 +1,70 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    types::{ChatPeerId, Command, SendMessage},
+};
+
+use crate::{
+    entities::Keywords,
+    services::{DeliveryService, NotesService, NotesServiceError},
+};
+
+pub async fn handle(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    delivery_service: Ref<DeliveryService>,
+    command: Command,
+    chat_id: ChatPeerId,
+) -> Result<(), RandomError> {
+    let keywords = Keywords::from(command.get_args().to_vec());
+    match notes_service.random(&keywords).await.map_err(RandomError::GetNote)? {
+        Some(note) => {
+            delivery_service.send(chat_id, note.data()).await?;
+            notes_service
+                .mark_used(note.id())
+                .await
+                .map_err(RandomError::MarkUsed)?;
+        }
+        None => {
+            client.execute(SendMessage::new(chat_id, "Not found")).await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum RandomError {
+    Execute(ExecuteError),
+    GetNote(NotesServiceError),
+    MarkUsed(NotesServiceError),
+}
+
+impl From<ExecuteError> for RandomError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for RandomError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::RandomError::*;
+        match self {
+            Execute(err) => err.fmt(out),
+            GetNote(err) => err.fmt(out),
+            MarkUsed(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for RandomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::RandomError::*;
+        Some(match self {
+            Execute(err) => err,
+            GetNote(err) => err,
+            MarkUsed(err) => err,
+        })
+    }
+}
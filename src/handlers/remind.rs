@@ -0,0 +1,85 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    types::{ChatPeerId, Command, SendMessage},
+};
+use time::OffsetDateTime;
+
+use super::format_datetime;
+use crate::{
+    entities::parse_reminder_time,
+    services::{NotesService, NotesServiceError, RemindersService, RemindersServiceError},
+};
+
+const USAGE: &str = "Usage: /remind <id> <when>, e.g. /remind 1 2h or /remind 1 tomorrow 9:00 (UTC)";
+
+pub async fn handle(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    reminders_service: Ref<RemindersService>,
+    command: Command,
+    chat_id: ChatPeerId,
+) -> Result<(), RemindError> {
+    let text = match command.get_args().split_first() {
+        Some((note_id, when)) if !when.is_empty() => match note_id.parse() {
+            Ok(note_id) => match parse_reminder_time(&when.join(" "), OffsetDateTime::now_utc()) {
+                Ok(send_at) => match notes_service.get(note_id).await.map_err(RemindError::GetNote)? {
+                    Some(_) => {
+                        let reminder = reminders_service
+                            .create(chat_id.into(), note_id, send_at)
+                            .await
+                            .map_err(RemindError::CreateReminder)?;
+                        format!(
+                            "Reminder {} set for {}",
+                            reminder.id(),
+                            format_datetime(reminder.send_at())
+                        )
+                    }
+                    None => String::from("Not found"),
+                },
+                Err(err) => format!("Could not parse time: {err}"),
+            },
+            Err(_) => String::from("Note ID is not an integer"),
+        },
+        _ => String::from(USAGE),
+    };
+    client.execute(SendMessage::new(chat_id, text)).await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum RemindError {
+    CreateReminder(RemindersServiceError),
+    Execute(ExecuteError),
+    GetNote(NotesServiceError),
+}
+
+impl From<ExecuteError> for RemindError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for RemindError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::RemindError::*;
+        match self {
+            CreateReminder(err) => err.fmt(out),
+            Execute(err) => err.fmt(out),
+            GetNote(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for RemindError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::RemindError::*;
+        Some(match self {
+            CreateReminder(err) => err,
+            Execute(err) => err,
+            GetNote(err) => err,
+        })
+    }
+}
@@ -0,0 +1,153 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    types::{
+        AnswerCallbackQuery, CallbackQuery, ChatPeerId, EditMessageText, InlineKeyboardButton, InlineKeyboardError,
+        InlineKeyboardMarkup, MaybeInaccessibleMessage, SendMessage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use super::format_datetime;
+use crate::{
+    entities::Reminder,
+    services::{RemindersService, RemindersServiceError},
+};
+
+pub async fn handle(
+    client: Ref<Client>,
+    reminders_service: Ref<RemindersService>,
+    chat_id: ChatPeerId,
+) -> Result<(), RemindersError> {
+    let reminders = list(&reminders_service, chat_id).await?;
+    let mut method = SendMessage::new(chat_id, format_list(&reminders));
+    if let Some(markup) = markup(&reminders)? {
+        method = method.with_reply_markup(markup);
+    }
+    client.execute(method).await?;
+    Ok(())
+}
+
+pub async fn is_callback(query: CallbackQuery) -> bool {
+    matches!(query.parse_data::<ReminderCallback>(), Ok(Some(_)))
+}
+
+pub async fn handle_callback(
+    client: Ref<Client>,
+    reminders_service: Ref<RemindersService>,
+    query: CallbackQuery,
+) -> Result<(), RemindersError> {
+    let (chat_id, message_id) = match query.message {
+        Some(MaybeInaccessibleMessage::Message(ref message)) => (message.chat.get_id(), message.id),
+        Some(MaybeInaccessibleMessage::InaccessibleMessage(ref message)) => (message.chat.get_id(), message.message_id),
+        None => {
+            client
+                .execute(AnswerCallbackQuery::new(query.id).with_text("Message is not available"))
+                .await?;
+            return Ok(());
+        }
+    };
+    let ReminderCallback::Cancel(id) = match query.parse_data::<ReminderCallback>() {
+        Ok(Some(callback)) => callback,
+        _ => return Ok(()),
+    };
+    let removed = reminders_service
+        .remove(id, Some(chat_id.into()))
+        .await
+        .map_err(RemindersError::Remove)?;
+    let reminders = list(&reminders_service, chat_id).await?;
+    let mut method = EditMessageText::for_chat_message(chat_id, message_id, format_list(&reminders));
+    if let Some(markup) = markup(&reminders)? {
+        method = method.with_reply_markup(markup);
+    }
+    client.execute(method).await?;
+    client
+        .execute(AnswerCallbackQuery::new(query.id).with_text(if removed { "Cancelled" } else { "Not found" }))
+        .await?;
+    Ok(())
+}
+
+async fn list(reminders_service: &RemindersService, chat_id: ChatPeerId) -> Result<Vec<Reminder>, RemindersError> {
+    reminders_service
+        .list(chat_id.into())
+        .await
+        .map_err(RemindersError::List)
+}
+
+fn format_list(reminders: &[Reminder]) -> String {
+    if reminders.is_empty() {
+        return String::from("No reminders");
+    }
+    let mut result = String::from("Reminders:\n");
+    for reminder in reminders {
+        result.push_str(&format!(
+            "\n{} · note {} · {}",
+            reminder.id(),
+            reminder.note_id(),
+            format_datetime(reminder.send_at())
+        ));
+    }
+    result
+}
+
+fn markup(reminders: &[Reminder]) -> Result<Option<InlineKeyboardMarkup>, RemindersError> {
+    if reminders.is_empty() {
+        return Ok(None);
+    }
+    let mut markup = InlineKeyboardMarkup::default();
+    for reminder in reminders {
+        let button = InlineKeyboardButton::for_callback_data_struct(
+            format!("Cancel {}", reminder.id()),
+            &ReminderCallback::Cancel(reminder.id()),
+        )
+        .map_err(RemindersError::Keyboard)?;
+        markup = markup.add_row([button]);
+    }
+    Ok(Some(markup))
+}
+
+#[derive(Deserialize, Serialize)]
+enum ReminderCallback {
+    #[serde(rename = "reminder_cancel")]
+    Cancel(i32),
+}
+
+#[derive(Debug)]
+pub enum RemindersError {
+    Execute(ExecuteError),
+    Keyboard(InlineKeyboardError),
+    List(RemindersServiceError),
+    Remove(RemindersServiceError),
+}
+
+impl From<ExecuteError> for RemindersError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for RemindersError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::RemindersError::*;
+        match self {
+            Execute(err) => err.fmt(out),
+            Keyboard(err) => err.fmt(out),
+            List(err) => err.fmt(out),
+            Remove(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for RemindersError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::RemindersError::*;
+        Some(match self {
+            Execute(err) => err,
+            Keyboard(err) => err,
+            List(err) => err,
+            Remove(err) => err,
+        })
+    }
+}
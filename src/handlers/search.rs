@@ -0,0 +1,365 @@
+use std::{error::Error, fmt};
+
+use carapax::{
+    Ref,
+    api::{Client, ExecuteError},
+    session::{Session, SessionError},
+    types::{
+        AnswerCallbackQuery, CallbackQuery, ChatPeerId, Command, EditMessageText, InlineKeyboardButton,
+        InlineKeyboardError, InlineKeyboardMarkup, MaybeInaccessibleMessage, SendMessage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entities::{Note, NoteQuery},
+    services::{DeliveryService, NoteSearch, NotesService, NotesServiceError},
+    session::SessionBackend,
+};
+
+const PAGE_SIZE: i64 = 10;
+/// Prefix of session keys holding the query of a results message
+const SESSION_KEY_PREFIX: &str = "search";
+
+pub async fn handle(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    mut session: Session<SessionBackend>,
+    command: Command,
+    chat_id: ChatPeerId,
+) -> Result<(), SearchError> {
+    let args = command.get_args();
+    let query = match NoteQuery::parse(args) {
+        Ok(query) if query.is_empty() => {
+            client
+                .execute(SendMessage::new(chat_id, "Search query is required"))
+                .await?;
+            return Ok(());
+        }
+        Ok(query) => query,
+        Err(err) => {
+            client
+                .execute(SendMessage::new(
+                    chat_id,
+                    format!("Could not parse search query: {err}"),
+                ))
+                .await?;
+            return Ok(());
+        }
+    };
+    let page = SearchPage::load(&notes_service, &query, 0).await?;
+    let mut method = SendMessage::new(chat_id, page.to_string());
+    if let Some(markup) = page.markup()? {
+        method = method.with_reply_markup(markup);
+    }
+    let message = client.execute(method).await?;
+    save_query(&mut session, message.id, args).await?;
+    Ok(())
+}
+
+pub async fn is_callback(query: CallbackQuery) -> bool {
+    matches!(query.parse_data::<SearchCallback>(), Ok(Some(_)))
+}
+
+pub async fn handle_callback(
+    client: Ref<Client>,
+    notes_service: Ref<NotesService>,
+    delivery_service: Ref<DeliveryService>,
+    mut session: Session<SessionBackend>,
+    query: CallbackQuery,
+) -> Result<(), SearchError> {
+    let (chat_id, message_id) = match query.message {
+        Some(MaybeInaccessibleMessage::Message(ref message)) => (message.chat.get_id(), message.id),
+        Some(MaybeInaccessibleMessage::InaccessibleMessage(ref message)) => (message.chat.get_id(), message.message_id),
+        None => {
+            client
+                .execute(AnswerCallbackQuery::new(query.id).with_text("Message is not available"))
+                .await?;
+            return Ok(());
+        }
+    };
+    let callback = match query.parse_data::<SearchCallback>() {
+        Ok(Some(callback)) => callback,
+        _ => return Ok(()),
+    };
+    let mut answer = AnswerCallbackQuery::new(query.id);
+    match callback {
+        SearchCallback::Page(number) => match load_query(&mut session, message_id).await? {
+            Some(query) => {
+                let page = SearchPage::load(&notes_service, &query, number).await?;
+                edit_page(&client, chat_id, message_id, page).await?;
+            }
+            None => answer = answer.with_text("Search has expired, run /search again"),
+        },
+        SearchCallback::Send(note_id) => match get_note(&notes_service, note_id).await? {
+            Some(note) => {
+                delivery_service
+                    .send(chat_id, note.data())
+                    .await
+                    .map_err(SearchError::Execute)?;
+                notes_service.mark_used(note_id).await.map_err(SearchError::MarkUsed)?;
+            }
+            None => answer = answer.with_text("Not found"),
+        },
+        SearchCallback::Open(note_id) => match get_note(&notes_service, note_id).await? {
+            Some(note) => {
+                let markup = InlineKeyboardMarkup::from(vec![vec![
+                    SearchCallback::Send(note_id).button("Send")?,
+                    SearchCallback::Delete {
+                        id: note_id,
+                        page: None,
+                    }
+                    .button("Delete")?,
+                ]]);
+                client
+                    .execute(SendMessage::new(chat_id, format_note(&note)).with_reply_markup(markup))
+                    .await?;
+            }
+            None => answer = answer.with_text("Not found"),
+        },
+        SearchCallback::Delete { id, page } => {
+            let removed = notes_service.remove(id).await.map_err(SearchError::RemoveNote)?;
+            answer = answer.with_text(if removed { "Removed" } else { "Not found" });
+            match page {
+                Some(number) => {
+                    if let Some(query) = load_query(&mut session, message_id).await? {
+                        let page = SearchPage::load(&notes_service, &query, number).await?;
+                        edit_page(&client, chat_id, message_id, page).await?;
+                    }
+                }
+                None => {
+                    client
+                        .execute(EditMessageText::for_chat_message(
+                            chat_id,
+                            message_id,
+                            format!("Note {id} removed"),
+                        ))
+                        .await?;
+                }
+            }
+        }
+    }
+    client.execute(answer).await?;
+    Ok(())
+}
+
+/// Stores a query of a results message, so its buttons keep working after another search
+async fn save_query(
+    session: &mut Session<SessionBackend>,
+    message_id: i64,
+    args: &[String],
+) -> Result<(), SearchError> {
+    session
+        .set(format!("{SESSION_KEY_PREFIX}:{message_id}"), &args)
+        .await
+        .map_err(SearchError::Session)
+}
+
+/// Returns a query of the given results message
+async fn load_query(session: &mut Session<SessionBackend>, message_id: i64) -> Result<Option<NoteQuery>, SearchError> {
+    let args: Option<Vec<String>> = session
+        .get(format!("{SESSION_KEY_PREFIX}:{message_id}"))
+        .await
+        .map_err(SearchError::Session)?;
+    Ok(args.and_then(|args| NoteQuery::parse(&args).ok()))
+}
+
+async fn get_note(notes_service: &NotesService, id: i32) -> Result<Option<Note>, SearchError> {
+    notes_service.get(id).await.map_err(SearchError::GetNote)
+}
+
+async fn edit_page(client: &Client, chat_id: ChatPeerId, message_id: i64, page: SearchPage) -> Result<(), SearchError> {
+    let mut method = EditMessageText::for_chat_message(chat_id, message_id, page.to_string());
+    if let Some(markup) = page.markup()? {
+        method = method.with_reply_markup(markup);
+    }
+    client.execute(method).await?;
+    Ok(())
+}
+
+fn format_note(note: &Note) -> String {
+    let mut result = format!(
+        "{} · {} · {}",
+        note.id(),
+        note.data().kind(),
+        note.keywords().as_string()
+    );
+    if let Some(title) = note.title() {
+        result.push_str(&format!(" · {title}"));
+    }
+    result
+}
+
+struct SearchPage {
+    number: i64,
+    search: NoteSearch,
+}
+
+impl SearchPage {
+    async fn load(notes_service: &NotesService, query: &NoteQuery, number: i64) -> Result<Self, SearchError> {
+        let mut number = number.max(0);
+        let mut search = notes_service
+            .search(query, number * PAGE_SIZE, PAGE_SIZE)
+            .await
+            .map_err(SearchError::Search)?;
+        if search.items.is_empty() && search.total > 0 {
+            // The page may become empty after its last note was deleted
+            number = (search.total - 1) / PAGE_SIZE;
+            search = notes_service
+                .search(query, number * PAGE_SIZE, PAGE_SIZE)
+                .await
+                .map_err(SearchError::Search)?;
+        }
+        Ok(Self { number, search })
+    }
+
+    fn total_pages(&self) -> i64 {
+        (self.search.total + PAGE_SIZE - 1) / PAGE_SIZE
+    }
+
+    fn markup(&self) -> Result<Option<InlineKeyboardMarkup>, SearchError> {
+        if self.search.items.is_empty() {
+            return Ok(None);
+        }
+        let mut markup = InlineKeyboardMarkup::default();
+        for note in &self.search.items {
+            let id = note.id();
+            markup = markup.add_row([
+                SearchCallback::Send(id).button(format!("{id}: Send"))?,
+                SearchCallback::Open(id).button("Open")?,
+                SearchCallback::Delete {
+                    id,
+                    page: Some(self.number),
+                }
+                .button("Delete")?,
+            ]);
+        }
+        let mut navigation = Vec::new();
+        if self.number > 0 {
+            navigation.push(SearchCallback::Page(self.number - 1).button("« Previous")?);
+        }
+        if self.number + 1 < self.total_pages() {
+            navigation.push(SearchCallback::Page(self.number + 1).button("Next »")?);
+        }
+        if !navigation.is_empty() {
+            markup = markup.add_row(navigation);
+        }
+        Ok(Some(markup))
+    }
+}
+
+impl fmt::Display for SearchPage {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.search.total == 0 {
+            return write!(out, "Nothing found");
+        }
+        writeln!(
+            out,
+            "Found {} (page {} of {})",
+            self.search.total,
+            self.number + 1,
+            self.total_pages().max(1)
+        )?;
+        for note in &self.search.items {
+            write!(out, "\n{}", format_note(note))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+enum SearchCallback {
+    #[serde(rename = "search_page")]
+    Page(i64),
+    #[serde(rename = "search_send")]
+    Send(i32),
+    #[serde(rename = "search_open")]
+    Open(i32),
+    #[serde(rename = "search_delete")]
+    Delete { id: i32, page: Option<i64> },
+}
+
+impl SearchCallback {
+    fn button<T>(&self, text: T) -> Result<InlineKeyboardButton, SearchError>
+    where
+        T: Into<String>,
+    {
+        InlineKeyboardButton::for_callback_data_struct(text, self).map_err(SearchError::Keyboard)
+    }
+}
+
+#[derive(Debug)]
+pub enum SearchError {
+    Execute(ExecuteError),
+    GetNote(NotesServiceError),
+    Keyboard(InlineKeyboardError),
+    MarkUsed(NotesServiceError),
+    RemoveNote(NotesServiceError),
+    Search(NotesServiceError),
+    Session(SessionError),
+}
+
+impl From<ExecuteError> for SearchError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::SearchError::*;
+        match self {
+            Execute(err) => err.fmt(out),
+            GetNote(err) => err.fmt(out),
+            Keyboard(err) => err.fmt(out),
+            MarkUsed(err) => err.fmt(out),
+            RemoveNote(err) => err.fmt(out),
+            Search(err) => err.fmt(out),
+            Session(err) => err.fmt(out),
+        }
+    }
+}
+
+impl Error for SearchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::SearchError::*;
+        Some(match self {
+            Execute(err) => err,
+            GetNote(err) => err,
+            Keyboard(err) => err,
+            MarkUsed(err) => err,
+            RemoveNote(err) => err,
+            Search(err) => err,
+            Session(err) => err,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use carapax::session::SessionManager;
+
+    use super::*;
+    use crate::session::MemoryBackend;
+
+    fn args(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| String::from(*item)).collect()
+    }
+
+    #[tokio::test]
+    async fn interleaved_searches() {
+        let manager = SessionManager::new(SessionBackend::Memory(MemoryBackend::default()));
+        let mut session = manager.get_session("1-1");
+        let first = args(&["rust"]);
+        let second = args(&["type:photo", "cat"]);
+        save_query(&mut session, 10, &first).await.unwrap();
+        save_query(&mut session, 11, &second).await.unwrap();
+
+        // Paging the older message still uses its own query
+        let query = load_query(&mut session, 10).await.unwrap();
+        assert_eq!(query, Some(NoteQuery::parse(&first).unwrap()));
+        let query = load_query(&mut session, 11).await.unwrap();
+        assert_eq!(query, Some(NoteQuery::parse(&second).unwrap()));
+        assert_eq!(load_query(&mut session, 12).await.unwrap(), None);
+    }
+}
@@ -1,8 +1,11 @@
 mod app;
 mod config;
+mod crypto;
+mod database;
 mod entities;
 mod handlers;
 mod migrations;
+mod render;
 mod services;
 mod session;
 
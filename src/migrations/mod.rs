@@ -1,14 +1,196 @@
-use refinery::{Error, Migration, Report, Runner};
-use tokio_postgres::Client;
+use std::{error::Error, fmt};
+
+use refinery::{Error as RefineryError, Migration, Report, Runner};
+use time::OffsetDateTime;
+use tokio_postgres::{Client, Error as PgError};
 
 mod versions;
 
-pub async fn run(client: &mut Client) -> Result<Report, Error> {
-    let migrations: Result<Vec<Migration>, Error> = versions::build()
+const HISTORY_TABLE: &str = "refinery_schema_history";
+
+pub async fn run(client: &mut Client) -> Result<Report, MigrationError> {
+    let runner = create_runner()?;
+    runner.run_async(client).await.map_err(MigrationError::Run)
+}
+
+/// Returns all versions along with the time they were applied at
+pub async fn status(client: &mut Client) -> Result<Vec<VersionStatus>, MigrationError> {
+    let applied = get_applied(client).await?;
+    Ok(versions::build()
+        .into_iter()
+        .enumerate()
+        .map(|(idx, version)| VersionStatus {
+            number: idx as u32,
+            applied_on: applied
+                .iter()
+                .find(|migration| migration.version() == idx as u32)
+                .and_then(|migration| migration.applied_on().copied()),
+            name: String::from(version.name()),
+        })
+        .collect())
+}
+
+/// Returns versions which are not applied yet
+///
+/// Fails when the database contains a version unknown to this build, e.g. after a downgrade.
+pub async fn pending(client: &mut Client) -> Result<Vec<VersionStatus>, MigrationError> {
+    let applied = get_applied(client).await?;
+    let versions = versions::build();
+    if let Some(migration) = applied
+        .iter()
+        .find(|migration| migration.version() as usize >= versions.len())
+    {
+        return Err(MigrationError::UnknownVersion(migration.version()));
+    }
+    Ok(versions
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| !applied.iter().any(|migration| migration.version() == *idx as u32))
+        .map(|(idx, version)| VersionStatus {
+            number: idx as u32,
+            name: String::from(version.name()),
+            applied_on: None,
+        })
+        .collect())
+}
+
+/// Returns SQL of versions which are not applied yet
+pub async fn pending_sql(client: &mut Client) -> Result<Vec<VersionSql>, MigrationError> {
+    let applied = get_applied(client).await?;
+    Ok(versions::build()
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| !applied.iter().any(|migration| migration.version() == *idx as u32))
+        .map(|(idx, version)| VersionSql {
+            number: idx as u32,
+            sql: version.build(),
+            name: String::from(version.name()),
+        })
+        .collect())
+}
+
+/// Returns SQL reverting the given number of the latest applied versions
+pub async fn rollback_sql(client: &mut Client, count: usize) -> Result<Vec<VersionSql>, MigrationError> {
+    let applied = get_applied(client).await?;
+    let versions = versions::build();
+    applied
+        .iter()
+        .rev()
+        .take(count)
+        .map(|migration| {
+            let number = migration.version();
+            let version = versions
+                .get(number as usize)
+                .ok_or(MigrationError::UnknownVersion(number))?;
+            Ok(VersionSql {
+                number,
+                sql: version
+                    .build_down()
+                    .ok_or_else(|| MigrationError::NoDownStep(String::from(version.name())))?,
+                name: String::from(version.name()),
+            })
+        })
+        .collect()
+}
+
+/// Reverts the given number of the latest applied versions
+///
+/// Every version is reverted in a separate transaction,
+/// so a failed step leaves the database at the previous version.
+pub async fn rollback(client: &mut Client, count: usize) -> Result<Vec<VersionSql>, MigrationError> {
+    let items = rollback_sql(client, count).await?;
+    for item in &items {
+        let transaction = client.transaction().await.map_err(MigrationError::Rollback)?;
+        transaction
+            .batch_execute(&item.sql)
+            .await
+            .map_err(MigrationError::Rollback)?;
+        transaction
+            .execute(
+                &format!("DELETE FROM {HISTORY_TABLE} WHERE version = $1"),
+                &[&(item.number as i32)],
+            )
+            .await
+            .map_err(MigrationError::Rollback)?;
+        transaction.commit().await.map_err(MigrationError::Rollback)?;
+    }
+    Ok(items)
+}
+
+fn create_runner() -> Result<Runner, MigrationError> {
+    let migrations: Result<Vec<Migration>, RefineryError> = versions::build()
         .into_iter()
         .enumerate()
         .map(|(idx, version)| Migration::unapplied(&format!("U{}__{}", idx, version.name()), &version.build()))
         .collect();
-    let runner = Runner::new(&migrations?);
-    runner.run_async(client).await
+    migrations
+        .map(|migrations| Runner::new(&migrations))
+        .map_err(MigrationError::Build)
+}
+
+/// Returns applied migrations ordered by version, the history table is created on the first run
+async fn get_applied(client: &mut Client) -> Result<Vec<Migration>, MigrationError> {
+    let exists: bool = client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&HISTORY_TABLE])
+        .await
+        .map_err(MigrationError::History)?
+        .get(0);
+    if !exists {
+        return Ok(Vec::new());
+    }
+    let runner = create_runner()?;
+    runner
+        .get_applied_migrations_async(client)
+        .await
+        .map_err(MigrationError::Run)
+}
+
+pub struct VersionStatus {
+    pub number: u32,
+    pub name: String,
+    pub applied_on: Option<OffsetDateTime>,
+}
+
+pub struct VersionSql {
+    pub number: u32,
+    pub name: String,
+    pub sql: String,
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Build(RefineryError),
+    History(PgError),
+    NoDownStep(String),
+    Rollback(PgError),
+    Run(RefineryError),
+    UnknownVersion(u32),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::MigrationError::*;
+        match self {
+            Build(err) => write!(out, "can not build migrations: {err}"),
+            History(err) => write!(out, "can not get schema history: {err}"),
+            NoDownStep(name) => write!(out, "version {name} can not be reverted"),
+            Rollback(err) => write!(out, "can not revert version: {err}"),
+            Run(err) => write!(out, "{err}"),
+            UnknownVersion(number) => write!(out, "applied version {number} is unknown"),
+        }
+    }
+}
+
+impl Error for MigrationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::MigrationError::*;
+        match self {
+            Build(err) => Some(err),
+            History(err) => Some(err),
+            NoDownStep(_) => None,
+            Rollback(err) => Some(err),
+            Run(err) => Some(err),
+            UnknownVersion(_) => None,
+        }
+    }
 }
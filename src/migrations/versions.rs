@@ -1,18 +1,39 @@
-use barrel::{Migration, backend::Pg, types};
+use barrel::{Migration, backend::Pg, functions::current_timestamp, types};
 
 macro_rules! version {
     ($builder:ident) => {
         Version::new(stringify!($builder), $builder)
     };
+    ($builder:ident, $down:ident) => {
+        Version::new(stringify!($builder), $builder).with_down($down)
+    };
 }
 
 pub fn build() -> Vec<Version> {
-    vec![version!(create_notes)]
+    vec![
+        version!(create_notes, drop_notes),
+        version!(add_notes_usage_count, remove_notes_usage_count),
+        version!(add_notes_title, remove_notes_title),
+        version!(add_notes_expires_at, remove_notes_expires_at),
+        version!(create_reminders, drop_reminders),
+        version!(add_notes_last_used_at, remove_notes_last_used_at),
+        version!(create_digests, drop_digests),
+        version!(add_notes_archive_path, remove_notes_archive_path),
+        version!(
+            convert_notes_to_jsonb_add_timestamps,
+            convert_notes_to_json_remove_timestamps
+        ),
+        version!(create_sessions, drop_sessions),
+        version!(add_reminders_attempts, remove_reminders_attempts),
+        version!(add_notes_archive_attempts, remove_notes_archive_attempts),
+    ]
 }
 
 pub struct Version {
     name: String,
     builder: Builder,
+    /// Reverts changes made by `builder`
+    down: Option<Builder>,
 }
 
 impl Version {
@@ -23,9 +44,15 @@ impl Version {
         Self {
             name: name.into(),
             builder,
+            down: None,
         }
     }
 
+    fn with_down(mut self, builder: Builder) -> Self {
+        self.down = Some(builder);
+        self
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -35,6 +62,11 @@ impl Version {
         let migration = builder();
         migration.make::<Pg>()
     }
+
+    /// Returns SQL of the reverse step, if defined
+    pub fn build_down(&self) -> Option<String> {
+        self.down.map(|builder| builder().make::<Pg>())
+    }
 }
 
 type Builder = fn() -> Migration;
@@ -48,3 +80,238 @@ fn create_notes() -> Migration {
     });
     migration
 }
+
+fn drop_notes() -> Migration {
+    let mut migration = Migration::new();
+    migration.drop_table("notes");
+    migration
+}
+
+fn add_notes_usage_count() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.add_column("usage_count", types::integer().default(0));
+    });
+    migration
+}
+
+fn remove_notes_usage_count() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("usage_count");
+    });
+    migration
+}
+
+fn add_notes_title() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.add_column("title", types::varchar(255).nullable(true));
+        table.add_column("description", types::text().nullable(true));
+    });
+    migration
+}
+
+fn remove_notes_title() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("title");
+        table.drop_column("description");
+    });
+    migration
+}
+
+fn add_notes_expires_at() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.add_column("expires_at", types::custom("TIMESTAMP WITH TIME ZONE").nullable(true));
+    });
+    migration
+}
+
+fn remove_notes_expires_at() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("expires_at");
+    });
+    migration
+}
+
+fn create_reminders() -> Migration {
+    let mut migration = Migration::new();
+    migration.create_table("reminders", |table| {
+        table.add_column("id", types::primary());
+        table.add_column("chat_id", types::custom("BIGINT"));
+        table.add_column(
+            "note_id",
+            types::custom("INTEGER REFERENCES notes (id) ON DELETE CASCADE"),
+        );
+        table.add_column("send_at", types::custom("TIMESTAMP WITH TIME ZONE"));
+    });
+    migration
+}
+
+fn drop_reminders() -> Migration {
+    let mut migration = Migration::new();
+    migration.drop_table("reminders");
+    migration
+}
+
+fn add_notes_last_used_at() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.add_column("last_used_at", types::custom("TIMESTAMP WITH TIME ZONE").nullable(true));
+    });
+    migration
+}
+
+fn remove_notes_last_used_at() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("last_used_at");
+    });
+    migration
+}
+
+fn create_digests() -> Migration {
+    let mut migration = Migration::new();
+    migration.create_table("digests", |table| {
+        table.add_column("chat_id", types::custom("BIGINT PRIMARY KEY"));
+        table.add_column("schedule", types::varchar(16));
+        table.add_column("mode", types::varchar(16));
+        table.add_column("size", types::integer());
+        table.add_column("next_at", types::custom("TIMESTAMP WITH TIME ZONE"));
+    });
+    migration
+}
+
+fn drop_digests() -> Migration {
+    let mut migration = Migration::new();
+    migration.drop_table("digests");
+    migration
+}
+
+fn add_notes_archive_path() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.add_column("archive_path", types::text().nullable(true));
+    });
+    migration
+}
+
+fn remove_notes_archive_path() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("archive_path");
+    });
+    migration
+}
+
+fn convert_notes_to_jsonb_add_timestamps() -> Migration {
+    let mut migration = Migration::new();
+    migration.inject_custom("ALTER TABLE notes ALTER COLUMN data TYPE JSONB USING data::jsonb");
+    migration.inject_custom("CREATE INDEX notes_keywords_idx ON notes USING GIN (keywords)");
+    // Existing rows get the time of the migration, creation time of older notes is unknown
+    migration.change_table("notes", |table| {
+        table.add_column(
+            "created_at",
+            types::custom("TIMESTAMP WITH TIME ZONE").default(current_timestamp()),
+        );
+        table.add_column(
+            "updated_at",
+            types::custom("TIMESTAMP WITH TIME ZONE").default(current_timestamp()),
+        );
+    });
+    migration
+}
+
+fn convert_notes_to_json_remove_timestamps() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("created_at");
+        table.drop_column("updated_at");
+    });
+    migration.inject_custom("DROP INDEX notes_keywords_idx");
+    migration.inject_custom("ALTER TABLE notes ALTER COLUMN data TYPE JSON USING data::json");
+    migration
+}
+
+/// Storage of the Postgres session backend
+fn create_sessions() -> Migration {
+    let mut migration = Migration::new();
+    migration.create_table("sessions", |table| {
+        table.add_column("id", types::custom("TEXT PRIMARY KEY"));
+        table.add_column(
+            "created_at",
+            types::custom("TIMESTAMP WITH TIME ZONE").default(current_timestamp()),
+        );
+    });
+    migration.create_table("session_values", |table| {
+        table.add_column(
+            "session_id",
+            types::custom("TEXT REFERENCES sessions (id) ON DELETE CASCADE"),
+        );
+        table.add_column("key", types::text());
+        table.add_column("value", types::binary());
+        table.set_primary_key(&["session_id", "key"]);
+    });
+    migration
+}
+
+fn drop_sessions() -> Migration {
+    let mut migration = Migration::new();
+    migration.drop_table("session_values");
+    migration.drop_table("sessions");
+    migration
+}
+
+fn add_reminders_attempts() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("reminders", |table| {
+        table.add_column("attempts", types::integer().default(0));
+    });
+    migration
+}
+
+fn remove_reminders_attempts() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("reminders", |table| {
+        table.drop_column("attempts");
+    });
+    migration
+}
+
+/// Failed attempts to archive a file, a note without `archive_retry_at` is not retried
+fn add_notes_archive_attempts() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.add_column("archive_attempts", types::integer().default(0));
+        table.add_column(
+            "archive_retry_at",
+            types::custom("TIMESTAMP WITH TIME ZONE").nullable(true),
+        );
+    });
+    migration
+}
+
+fn remove_notes_archive_attempts() -> Migration {
+    let mut migration = Migration::new();
+    migration.change_table("notes", |table| {
+        table.drop_column("archive_attempts");
+        table.drop_column("archive_retry_at");
+    });
+    migration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn down_steps() {
+        for version in build() {
+            assert!(version.build_down().is_some(), "{} can not be reverted", version.name());
+        }
+        assert_eq!(version!(drop_notes).build_down(), None);
+    }
+}
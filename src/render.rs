@@ -0,0 +1,149 @@
+use carapax::types::ParseMode;
+use serde::Deserialize;
+
+const ELLIPSIS: char = '…';
+
+/// Characters which must be escaped in MarkdownV2 outside of code entities
+const MARKDOWN_V2_RESERVED: &[char] = &[
+    '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!', '\\',
+];
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    Html,
+    #[default]
+    MarkdownV2,
+}
+
+impl RenderMode {
+    pub fn parse_mode(self) -> ParseMode {
+        match self {
+            Self::Html => ParseMode::Html,
+            Self::MarkdownV2 => ParseMode::MarkdownV2,
+        }
+    }
+
+    /// Escapes user content to be placed into a message as is
+    pub fn escape(self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        for c in text.chars() {
+            self.push_escaped(&mut result, c);
+        }
+        result
+    }
+
+    /// Wraps a text into a monospace entity
+    pub fn code(self, text: &str) -> String {
+        match self {
+            Self::Html => format!("<code>{}</code>", self.escape(text)),
+            Self::MarkdownV2 => {
+                let mut result = String::from("`");
+                for c in text.chars() {
+                    if c == '`' || c == '\\' {
+                        result.push('\\');
+                    }
+                    result.push(c);
+                }
+                result.push('`');
+                result
+            }
+        }
+    }
+
+    /// Escapes a text and truncates it, so that the result takes no more than `max_len` UTF-16 code units
+    ///
+    /// Truncation never splits an escape sequence, an ellipsis is appended when the text is truncated.
+    pub fn truncate(self, text: &str, max_len: usize) -> String {
+        let escaped = self.escape(text);
+        if utf16_len(&escaped) <= max_len {
+            return escaped;
+        }
+        let max_len = max_len.saturating_sub(ELLIPSIS.len_utf16());
+        let mut result = String::new();
+        let mut len = 0;
+        let mut buf = String::new();
+        for c in text.chars() {
+            buf.clear();
+            self.push_escaped(&mut buf, c);
+            let buf_len = utf16_len(&buf);
+            if len + buf_len > max_len {
+                break;
+            }
+            result.push_str(&buf);
+            len += buf_len;
+        }
+        result.push(ELLIPSIS);
+        result
+    }
+
+    fn push_escaped(self, buf: &mut String, c: char) {
+        match self {
+            Self::Html => match c {
+                '&' => buf.push_str("&amp;"),
+                '<' => buf.push_str("&lt;"),
+                '>' => buf.push_str("&gt;"),
+                '"' => buf.push_str("&quot;"),
+                c => buf.push(c),
+            },
+            Self::MarkdownV2 => {
+                if MARKDOWN_V2_RESERVED.contains(&c) {
+                    buf.push('\\');
+                }
+                buf.push(c);
+            }
+        }
+    }
+}
+
+/// Returns length of a text in UTF-16 code units, the same way as Telegram does
+pub fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_markdown_v2() {
+        assert_eq!(
+            RenderMode::MarkdownV2.escape("snake_case *bold* [a](b) ~x~ `c` >q #h +-=|{}.!\\"),
+            r"snake\_case \*bold\* \[a\]\(b\) \~x\~ \`c\` \>q \#h \+\-\=\|\{\}\.\!\\"
+        );
+        assert_eq!(RenderMode::MarkdownV2.escape("plain text"), "plain text");
+    }
+
+    #[test]
+    fn escape_html() {
+        assert_eq!(
+            RenderMode::Html.escape(r#"<b>"tom" & jerry</b>"#),
+            "&lt;b&gt;&quot;tom&quot; &amp; jerry&lt;/b&gt;"
+        );
+        assert_eq!(RenderMode::Html.escape("snake_case-1.0"), "snake_case-1.0");
+    }
+
+    #[test]
+    fn code() {
+        assert_eq!(RenderMode::MarkdownV2.code("a`b\\c_d"), r"`a\`b\\c_d`");
+        assert_eq!(RenderMode::Html.code("1 < 2"), "<code>1 &lt; 2</code>");
+    }
+
+    #[test]
+    fn length_in_utf16() {
+        assert_eq!(utf16_len("abc"), 3);
+        assert_eq!(utf16_len("абв"), 3);
+        assert_eq!(utf16_len("😀"), 2);
+        assert_eq!(utf16_len(""), 0);
+    }
+
+    #[test]
+    fn truncate() {
+        assert_eq!(RenderMode::MarkdownV2.truncate("a.b", 4), r"a\.b");
+        assert_eq!(RenderMode::MarkdownV2.truncate("a.b.c", 4), r"a\.…");
+        assert_eq!(RenderMode::MarkdownV2.truncate("ab.c", 4), "ab…");
+        assert_eq!(RenderMode::MarkdownV2.truncate("😀😀😀", 5), "😀😀…");
+        assert_eq!(RenderMode::Html.truncate("a&b", 6), "a…");
+        assert_eq!(RenderMode::Html.truncate("a&b", 7), "a&amp;b");
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    path::{Component, Path, PathBuf},
+};
+
+use tokio::fs;
+
+/// Stores files in a local directory
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub async fn put(&self, key: &str, data: &[u8]) -> Result<(), IoError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, data).await
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, IoError> {
+        fs::read(self.path(key)?).await
+    }
+
+    /// Returns a path of a file, keys must not point outside of the root directory
+    fn path(&self, key: &str) -> Result<PathBuf, IoError> {
+        let key = Path::new(key);
+        if key.components().all(|x| matches!(x, Component::Normal(_))) {
+            Ok(self.root.join(key))
+        } else {
+            Err(IoError::new(IoErrorKind::InvalidInput, "invalid archive key"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn put_get() {
+        let root = std::env::temp_dir().join(format!("assistant-archive-{}", std::process::id()));
+        let storage = LocalStorage::new(&root);
+        storage.put("notes/1.jpg", b"data").await.unwrap();
+        assert_eq!(storage.get("notes/1.jpg").await.unwrap(), b"data");
+        assert!(storage.get("notes/2.jpg").await.is_err());
+        assert!(storage.put("../1.jpg", b"data").await.is_err());
+        assert!(storage.put("/tmp/1.jpg", b"data").await.is_err());
+        fs::remove_dir_all(root).await.unwrap();
+    }
+}
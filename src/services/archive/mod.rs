@@ -0,0 +1,287 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{Cursor, Error as IoError},
+    path::Path,
+    time::Duration,
+};
+
+use carapax::{
+    api::{Client, DownloadFileError, ExecuteError},
+    types::{ChatPeerId, DeleteMessage, GetFile, InputFileReader},
+};
+use futures_util::TryStreamExt;
+use reqwest::Error as HttpError;
+use tokio::time::interval;
+
+use crate::{
+    config::ArchiveConfig,
+    entities::{Note, NoteData, NoteDataError},
+    services::{DeliveryService, NotesService, NotesServiceError, is_permanent_error},
+};
+
+pub use self::{
+    local::LocalStorage,
+    s3::{S3Error, S3Storage},
+};
+
+mod local;
+mod s3;
+
+/// Delay before the second attempt to archive a file
+const RETRY_DELAY: Duration = Duration::from_secs(3600);
+/// A file is not archived after this number of failed attempts
+const MAX_ATTEMPTS: i32 = 8;
+
+/// Keeps copies of files of media notes
+///
+/// A `file_id` is valid for a single bot only, so files must be uploaded again
+/// after a token is revoked, see `ArchiveService::restore`.
+pub struct ArchiveService {
+    client: Client,
+    delivery_service: DeliveryService,
+    notes_service: NotesService,
+    storage: ArchiveStorage,
+}
+
+impl ArchiveService {
+    pub fn new(
+        client: Client,
+        delivery_service: DeliveryService,
+        notes_service: NotesService,
+        config: ArchiveConfig,
+    ) -> Self {
+        Self {
+            client,
+            delivery_service,
+            notes_service,
+            storage: ArchiveStorage::from(config),
+        }
+    }
+
+    /// Periodically archives new notes
+    pub async fn run(&self, period: Duration) {
+        let mut interval = interval(period);
+        loop {
+            interval.tick().await;
+            match self.archive_pending().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Archived {count} notes"),
+                Err(err) => log::error!("Could not archive notes: {err}"),
+            }
+        }
+    }
+
+    /// Downloads files of notes which are not archived yet
+    ///
+    /// Returns a number of archived notes, failed notes are logged and retried later,
+    /// unless they can never succeed, e.g. when a file is too big to download.
+    pub async fn archive_pending(&self) -> Result<usize, ArchiveError> {
+        let notes = self
+            .notes_service
+            .list_unarchived()
+            .await
+            .map_err(ArchiveError::GetNotes)?;
+        let mut count = 0;
+        for note in notes {
+            match self.archive(&note).await {
+                Ok(()) => count += 1,
+                Err(err) => {
+                    log::error!("Could not archive note {}: {err}", note.id());
+                    if let Err(err) = self
+                        .notes_service
+                        .set_archive_failed(note.id(), !err.is_permanent(), RETRY_DELAY, MAX_ATTEMPTS)
+                        .await
+                    {
+                        log::error!("Could not record failed archiving of note {}: {err}", note.id());
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    async fn archive(&self, note: &Note) -> Result<(), ArchiveError> {
+        let file = note.data().file().ok_or(ArchiveError::NoFile)?;
+        let file_path = self
+            .client
+            .execute(GetFile::new(file.file_id.clone()))
+            .await?
+            .file_path
+            .ok_or(ArchiveError::NoFilePath)?;
+        let data: Vec<u8> = self
+            .client
+            .download_file(&file_path)
+            .await
+            .map_err(ArchiveError::Download)?
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await
+            .map_err(ArchiveError::Http)?;
+        let key = archive_key(note, &file_path);
+        self.storage.put(&key, &data).await?;
+        self.notes_service
+            .set_archive_path(note.id(), &key)
+            .await
+            .map_err(ArchiveError::UpdateNote)
+    }
+
+    /// Uploads archived files using the current bot token and replaces identifiers of files
+    ///
+    /// Telegram allows to upload a file only by sending it,
+    /// so files are sent to the given chat and removed right after.
+    pub async fn restore(&self, chat_id: ChatPeerId) -> Result<usize, ArchiveError> {
+        let notes = self
+            .notes_service
+            .list_archived()
+            .await
+            .map_err(ArchiveError::GetNotes)?;
+        let mut count = 0;
+        for note in notes {
+            let note_id = note.id();
+            match self.restore_note(chat_id, note).await {
+                Ok(()) => count += 1,
+                Err(err) => log::error!("Could not restore note {note_id}: {err}"),
+            }
+        }
+        Ok(count)
+    }
+
+    async fn restore_note(&self, chat_id: ChatPeerId, note: Note) -> Result<(), ArchiveError> {
+        let key = note.archive_path().ok_or(ArchiveError::NoFile)?;
+        let file_name = note
+            .data()
+            .file()
+            .and_then(|file| file.file_name.clone())
+            .or_else(|| Path::new(key).file_name().map(|x| x.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| String::from(key));
+        let data = self.storage.get(key).await?;
+        let reader = InputFileReader::new(Cursor::new(data)).with_file_name(file_name);
+        let message = self
+            .delivery_service
+            .send_file(chat_id, note.data().kind(), reader.into())
+            .await?;
+        let message_id = message.id;
+        let data = NoteData::try_from(message.data).map_err(ArchiveError::UploadedFile)?;
+        if data.kind() != note.data().kind() {
+            return Err(ArchiveError::KindChanged);
+        }
+        self.notes_service
+            .update(&note.with_data(data))
+            .await
+            .map_err(ArchiveError::UpdateNote)?;
+        if let Err(err) = self.client.execute(DeleteMessage::new(chat_id, message_id)).await {
+            log::warn!("Could not delete uploaded file: {err}");
+        }
+        Ok(())
+    }
+}
+
+/// Returns a key of a file in a storage, e.g. `notes/1.jpg`
+fn archive_key(note: &Note, file_path: &str) -> String {
+    match Path::new(file_path).extension().and_then(|x| x.to_str()) {
+        Some(extension) => format!("notes/{}.{extension}", note.id()),
+        None => format!("notes/{}", note.id()),
+    }
+}
+
+enum ArchiveStorage {
+    Local(LocalStorage),
+    S3(S3Storage),
+}
+
+impl ArchiveStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> Result<(), ArchiveError> {
+        match self {
+            Self::Local(storage) => storage.put(key, data).await.map_err(ArchiveError::Local),
+            Self::S3(storage) => storage.put(key, data).await.map_err(ArchiveError::S3),
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>, ArchiveError> {
+        match self {
+            Self::Local(storage) => storage.get(key).await.map_err(ArchiveError::Local),
+            Self::S3(storage) => storage.get(key).await.map_err(ArchiveError::S3),
+        }
+    }
+}
+
+impl From<ArchiveConfig> for ArchiveStorage {
+    fn from(config: ArchiveConfig) -> Self {
+        match config {
+            ArchiveConfig::Local { path } => Self::Local(LocalStorage::new(path)),
+            ArchiveConfig::S3(config) => Self::S3(S3Storage::new(config)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Download(DownloadFileError),
+    Execute(ExecuteError),
+    GetNotes(NotesServiceError),
+    Http(HttpError),
+    KindChanged,
+    Local(IoError),
+    NoFile,
+    NoFilePath,
+    S3(S3Error),
+    UpdateNote(NotesServiceError),
+    UploadedFile(NoteDataError),
+}
+
+impl ArchiveError {
+    /// Whether archiving would fail again
+    fn is_permanent(&self) -> bool {
+        use self::ArchiveError::*;
+        match self {
+            Execute(err) => is_permanent_error(err),
+            KindChanged | NoFile | NoFilePath => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<ExecuteError> for ArchiveError {
+    fn from(err: ExecuteError) -> Self {
+        Self::Execute(err)
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::ArchiveError::*;
+        match self {
+            Download(err) => write!(out, "download file: {err}"),
+            Execute(err) => err.fmt(out),
+            GetNotes(err) => err.fmt(out),
+            Http(err) => write!(out, "download file: {err}"),
+            KindChanged => write!(out, "uploaded file has a different type"),
+            Local(err) => write!(out, "local archive: {err}"),
+            NoFile => write!(out, "note has no file"),
+            NoFilePath => write!(out, "file can not be downloaded"),
+            S3(err) => err.fmt(out),
+            UpdateNote(err) => err.fmt(out),
+            UploadedFile(err) => write!(out, "uploaded file: {err}"),
+        }
+    }
+}
+
+impl Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::ArchiveError::*;
+        Some(match self {
+            Download(err) => err,
+            Execute(err) => err,
+            GetNotes(err) => err,
+            Http(err) => err,
+            KindChanged => return None,
+            Local(err) => err,
+            NoFile => return None,
+            NoFilePath => return None,
+            S3(err) => err,
+            UpdateNote(err) => err,
+            UploadedFile(err) => err,
+        })
+    }
+}
@@ -0,0 +1,116 @@
+use std::{marker::PhantomData, time::Duration};
+
+use carapax::{
+    api::Client,
+    dialogue::DialogueState,
+    session::{SessionManager, backend::SessionBackend as _},
+    types::{ChatPeerId, SendMessage},
+};
+use tokio::time::interval;
+
+use crate::{
+    services::NotesService,
+    session::{self, SessionBackend},
+};
+
+/// Periodically removes expired notes
+pub struct NotesCollector {
+    notes_service: NotesService,
+    period: Duration,
+}
+
+impl NotesCollector {
+    pub fn new(notes_service: NotesService, period: Duration) -> Self {
+        Self { notes_service, period }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval = interval(self.period);
+        loop {
+            interval.tick().await;
+            match self.notes_service.remove_expired().await {
+                Ok(0) => {}
+                Ok(count) => log::info!("Removed {count} expired notes"),
+                Err(err) => log::error!("Could not remove expired notes: {err}"),
+            }
+        }
+    }
+}
+
+/// Periodically cancels dialogues which have not received a message within the timeout
+pub struct DialogueCollector<S> {
+    client: Client,
+    session_backend: SessionBackend,
+    session_manager: SessionManager<SessionBackend>,
+    timeout: Duration,
+    period: Duration,
+    state: PhantomData<S>,
+}
+
+impl<S: DialogueState> DialogueCollector<S> {
+    pub fn new(client: Client, session_backend: SessionBackend, timeout: Duration, period: Duration) -> Self {
+        Self {
+            client,
+            session_manager: SessionManager::new(session_backend.clone()),
+            session_backend,
+            timeout,
+            period,
+            state: PhantomData,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval = interval(self.period);
+        loop {
+            interval.tick().await;
+            let session_ids = match self.session_backend.get_sessions().await {
+                Ok(session_ids) => session_ids,
+                Err(err) => {
+                    log::error!("Could not get sessions: {err}");
+                    continue;
+                }
+            };
+            for session_id in session_ids {
+                self.collect(session_id).await;
+            }
+        }
+    }
+
+    async fn collect(&self, session_id: String) {
+        // Session ID is "{chat_id}-{user_id}", chat ID may be negative
+        let chat_id = match session_id
+            .rsplit_once('-')
+            .and_then(|(chat_id, _)| chat_id.parse::<i64>().ok())
+        {
+            Some(chat_id) => ChatPeerId::from(chat_id),
+            None => return,
+        };
+        let mut session = self.session_manager.get_session(session_id.as_str());
+        match session::is_dialogue_idle(&mut session, self.timeout).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                log::error!("Could not check dialogue of session {session_id}: {err}");
+                return;
+            }
+        }
+        let is_active = match session.get::<_, S>(S::session_key()).await {
+            Ok(state) => state.is_some(),
+            Err(err) => {
+                log::error!("Could not get dialogue of session {session_id}: {err}");
+                return;
+            }
+        };
+        if let Err(err) = session::clear_dialogue::<S>(&mut session).await {
+            log::error!("Could not cancel dialogue of session {session_id}: {err}");
+            return;
+        }
+        if !is_active {
+            return;
+        }
+        let text = session::dialogue_timeout_message::<S>(self.timeout);
+        if let Err(err) = self.client.execute(SendMessage::new(chat_id, text)).await {
+            log::error!("Could not notify about cancelled dialogue in chat {chat_id}: {err}");
+        }
+    }
+}
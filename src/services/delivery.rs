@@ -0,0 +1,65 @@
+use carapax::{
+    api::{Client, ExecuteError},
+    types::{
+        ChatPeerId, InputFile, Message, SendAnimation, SendAudio, SendDocument, SendLocation, SendMessage, SendPhoto,
+        SendVideo, SendVoice,
+    },
+};
+
+use crate::entities::{NoteData, NoteKind};
+
+/// Whether a request would fail again, e.g. when the bot is blocked or a file is too big
+pub fn is_permanent_error(err: &ExecuteError) -> bool {
+    match err {
+        ExecuteError::Response(err) => !err.can_retry() && matches!(err.error_code(), Some(400 | 403)),
+        ExecuteError::Payload(_) => true,
+        ExecuteError::Http(_) | ExecuteError::TooManyRequests => false,
+    }
+}
+
+#[derive(Clone)]
+pub struct DeliveryService {
+    client: Client,
+}
+
+impl DeliveryService {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    pub async fn send(&self, chat_id: ChatPeerId, data: &NoteData) -> Result<Message, ExecuteError> {
+        match data {
+            NoteData::Location { latitude, longitude } => {
+                self.client
+                    .execute(SendLocation::new(chat_id, *latitude, *longitude))
+                    .await
+            }
+            NoteData::Text(text) => self.client.execute(SendMessage::new(chat_id, text.clone())).await,
+            _ => {
+                let file_id = data.file().map(|file| file.file_id.clone()).unwrap_or_default();
+                self.send_file(chat_id, data.kind(), InputFile::file_id(file_id)).await
+            }
+        }
+    }
+
+    /// Sends a file using a method which corresponds to the kind of a note
+    ///
+    /// Kinds without a file are sent as documents.
+    pub async fn send_file(
+        &self,
+        chat_id: ChatPeerId,
+        kind: NoteKind,
+        file: InputFile,
+    ) -> Result<Message, ExecuteError> {
+        match kind {
+            NoteKind::Animation => self.client.execute(SendAnimation::new(file, chat_id)).await,
+            NoteKind::Audio => self.client.execute(SendAudio::new(chat_id, file)).await,
+            NoteKind::Document | NoteKind::Location | NoteKind::Text => {
+                self.client.execute(SendDocument::new(chat_id, file)).await
+            }
+            NoteKind::Photo => self.client.execute(SendPhoto::new(chat_id, file)).await,
+            NoteKind::Video => self.client.execute(SendVideo::new(chat_id, file)).await,
+            NoteKind::Voice => self.client.execute(SendVoice::new(chat_id, file)).await,
+        }
+    }
+}
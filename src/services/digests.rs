@@ -0,0 +1,127 @@
+use std::{error::Error, fmt};
+
+use deadpool_postgres::{Object as PgObject, Pool as PgPool, PoolError};
+use time::OffsetDateTime;
+use tokio_postgres::Error as PgError;
+
+use crate::entities::{Digest, DigestError, DigestSettings};
+
+#[derive(Clone)]
+pub struct DigestsService {
+    pool: PgPool,
+}
+
+impl DigestsService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn client(&self) -> Result<PgObject, DigestsServiceError> {
+        self.pool.get().await.map_err(DigestsServiceError::Connect)
+    }
+
+    pub async fn get(&self, chat_id: i64) -> Result<Option<Digest>, DigestsServiceError> {
+        self.client()
+            .await?
+            .query_opt("SELECT * FROM digests WHERE chat_id = $1", &[&chat_id])
+            .await
+            .map_err(DigestsServiceError::Get)?
+            .map(Digest::try_from)
+            .transpose()
+            .map_err(DigestsServiceError::MapDigest)
+    }
+
+    /// Returns digests which should be sent now
+    pub async fn list_due(&self) -> Result<Vec<Digest>, DigestsServiceError> {
+        self.client()
+            .await?
+            .query("SELECT * FROM digests WHERE next_at <= now()", &[])
+            .await
+            .map_err(DigestsServiceError::Get)?
+            .into_iter()
+            .map(Digest::try_from)
+            .collect::<Result<Vec<Digest>, DigestError>>()
+            .map_err(DigestsServiceError::MapDigest)
+    }
+
+    /// Creates or replaces digest settings of a chat
+    pub async fn set(
+        &self,
+        chat_id: i64,
+        settings: DigestSettings,
+        next_at: OffsetDateTime,
+    ) -> Result<(), DigestsServiceError> {
+        self.client().await?
+            .execute(
+                "INSERT INTO digests (chat_id, schedule, mode, size, next_at) VALUES ($1, $2, $3, $4, $5) \
+                 ON CONFLICT (chat_id) DO UPDATE \
+                 SET schedule = EXCLUDED.schedule, mode = EXCLUDED.mode, size = EXCLUDED.size, next_at = EXCLUDED.next_at",
+                &[
+                    &chat_id,
+                    &settings.schedule.as_str(),
+                    &settings.mode.as_str(),
+                    &settings.size,
+                    &next_at,
+                ],
+            )
+            .await
+            .map_err(DigestsServiceError::Set)?;
+        Ok(())
+    }
+
+    pub async fn reschedule(&self, chat_id: i64, next_at: OffsetDateTime) -> Result<(), DigestsServiceError> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE digests SET next_at = $2 WHERE chat_id = $1",
+                &[&chat_id, &next_at],
+            )
+            .await
+            .map_err(DigestsServiceError::Set)?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, chat_id: i64) -> Result<bool, DigestsServiceError> {
+        self.client()
+            .await?
+            .execute("DELETE FROM digests WHERE chat_id = $1", &[&chat_id])
+            .await
+            .map(|affected_rows| affected_rows != 0)
+            .map_err(DigestsServiceError::Remove)
+    }
+}
+
+#[derive(Debug)]
+pub enum DigestsServiceError {
+    Connect(PoolError),
+    Get(PgError),
+    MapDigest(DigestError),
+    Remove(PgError),
+    Set(PgError),
+}
+
+impl fmt::Display for DigestsServiceError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::DigestsServiceError::*;
+        match self {
+            Connect(err) => write!(out, "get database connection: {err}"),
+            Get(err) => write!(out, "get digest: {err}"),
+            MapDigest(err) => write!(out, "map digest: {err}"),
+            Remove(err) => write!(out, "remove digest: {err}"),
+            Set(err) => write!(out, "set digest: {err}"),
+        }
+    }
+}
+
+impl Error for DigestsServiceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::DigestsServiceError::*;
+        Some(match self {
+            Connect(err) => err,
+            Get(err) => err,
+            MapDigest(err) => err,
+            Remove(err) => err,
+            Set(err) => err,
+        })
+    }
+}
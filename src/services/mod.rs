@@ -1,3 +1,17 @@
+mod archive;
+mod collector;
+mod delivery;
+mod digests;
 mod notes;
+mod reminders;
+mod scheduler;
 
-pub use self::notes::{NotesService, NotesServiceError};
+pub use self::{
+    archive::{ArchiveError, ArchiveService},
+    collector::{DialogueCollector, NotesCollector},
+    delivery::{DeliveryService, is_permanent_error},
+    digests::{DigestsService, DigestsServiceError},
+    notes::{NoteList, NoteSearch, NotesService, NotesServiceError},
+    reminders::{RemindersService, RemindersServiceError},
+    scheduler::{DigestScheduler, ReminderScheduler},
+};
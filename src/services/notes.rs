@@ -1,39 +1,210 @@
-use crate::entities::{Keywords, NewNote, Note, NoteDataError, NoteError, NoteInfoList};
-use std::{error::Error, fmt, sync::Arc};
-use tokio_postgres::{Client as PgClient, Error as PgError};
+use crate::{
+    crypto::Cipher,
+    entities::{
+        DigestMode, DuplicateKey, Keywords, NewNote, Note, NoteData, NoteDataError, NoteError, NoteInfoList, NoteKind,
+        NoteOrder, NoteQuery,
+    },
+};
+use deadpool_postgres::{GenericClient, Object as PgObject, Pool as PgPool, PoolError};
+use std::{error::Error, fmt, sync::Arc, time::Duration};
+use tokio_postgres::{Error as PgError, Row, types::ToSql};
+
+/// Number of notes locked at once by `rotate-keys` and `upgrade-notes`
+const REWRITE_BATCH_SIZE: i64 = 100;
 
 #[derive(Clone)]
 pub struct NotesService {
-    client: Arc<PgClient>,
+    pool: PgPool,
+    cipher: Option<Arc<Cipher>>,
 }
 
 impl NotesService {
-    pub fn new(client: Arc<PgClient>) -> Self {
-        Self { client }
+    pub fn new(pool: PgPool, cipher: Option<Arc<Cipher>>) -> Self {
+        Self { pool, cipher }
+    }
+
+    async fn client(&self) -> Result<PgObject, NotesServiceError> {
+        self.pool.get().await.map_err(NotesServiceError::Connect)
     }
 
     pub async fn create(&self, note: NewNote) -> Result<(), NotesServiceError> {
-        let data = note.data().as_json().map_err(NotesServiceError::Serialize)?;
-        self.client
+        let encoded = note
+            .encode(self.cipher.as_deref())
+            .map_err(NotesServiceError::Serialize)?;
+        self.client()
+            .await?
             .execute(
-                "INSERT INTO notes (data, keywords) VALUES ($1, $2)",
-                &[&data, &note.keywords().as_ref()],
+                "INSERT INTO notes (data, keywords, title, description, expires_at) VALUES ($1, $2, $3, $4, $5)",
+                &[
+                    &encoded.data,
+                    &encoded.keywords,
+                    &encoded.title,
+                    &encoded.description,
+                    &note.expires_at(),
+                ],
             )
             .await
             .map_err(NotesServiceError::Create)?;
         Ok(())
     }
 
-    pub async fn get_list(&self) -> Result<NoteInfoList, NotesServiceError> {
-        self.client
-            .query("SELECT id, keywords FROM notes ORDER BY id ASC", &[])
+    /// Returns a note with the same content, see `NoteData::duplicate_key`
+    pub async fn find_duplicate(&self, data: &NoteData) -> Result<Option<Note>, NotesServiceError> {
+        let key = data.duplicate_key();
+        // Encrypted notes store a blinded key instead
+        let blinded: Vec<String> = match (&self.cipher, &key) {
+            (Some(cipher), Some(key)) => cipher.blind_all(&[key.to_string()]).into_iter().flatten().collect(),
+            _ => Vec::new(),
+        };
+        let row = match key {
+            Some(DuplicateKey::File { kind, file_unique_id }) => self
+                .client()
+                .await?
+                .query_opt(
+                    "SELECT * FROM notes \
+                     WHERE (data->$1->>'file_unique_id' = $2 OR data->'envelope'->>'duplicate' = ANY($3)) \
+                     AND (expires_at IS NULL OR expires_at > now()) ORDER BY id ASC LIMIT 1",
+                    &[&kind.tag(), &file_unique_id, &blinded],
+                )
+                .await
+                .map_err(NotesServiceError::FindDuplicate)?,
+            Some(DuplicateKey::Text(text)) => self
+                .client()
+                .await?
+                .query_opt(
+                    "SELECT * FROM notes \
+                     WHERE (lower(btrim(regexp_replace(data->>'Text', '\\s+', ' ', 'g'))) = $1 \
+                     OR data->'envelope'->>'duplicate' = ANY($2)) \
+                     AND (expires_at IS NULL OR expires_at > now()) ORDER BY id ASC LIMIT 1",
+                    &[&text, &blinded],
+                )
+                .await
+                .map_err(NotesServiceError::FindDuplicate)?,
+            None => None,
+        };
+        row.map(|row| self.decode(row)).transpose()
+    }
+
+    pub async fn get(&self, id: i32) -> Result<Option<Note>, NotesServiceError> {
+        self.client()
+            .await?
+            .query_opt(
+                "SELECT * FROM notes WHERE id = $1 AND (expires_at IS NULL OR expires_at > now())",
+                &[&id],
+            )
+            .await
+            .map_err(NotesServiceError::Get)?
+            .map(|row| self.decode(row))
+            .transpose()
+    }
+
+    /// Returns notes for a digest
+    ///
+    /// Picked notes are marked as shown, so forgotten notes rotate between digests.
+    pub async fn digest(&self, mode: DigestMode, limit: i64) -> Result<Vec<Note>, NotesServiceError> {
+        let query = format!(
+            "UPDATE notes SET last_used_at = now() WHERE id IN (\
+                SELECT id FROM notes WHERE expires_at IS NULL OR expires_at > now() ORDER BY {} LIMIT $1\
+             ) RETURNING *",
+            mode.as_sql()
+        );
+        let rows = self
+            .client()
+            .await?
+            .query(&query, &[&limit])
+            .await
+            .map_err(NotesServiceError::Digest)?;
+        self.decode_all(rows)
+    }
+
+    /// Returns a page of live notes along with the number of all matching notes
+    pub async fn get_list(
+        &self,
+        kind: Option<NoteKind>,
+        order: NoteOrder,
+        offset: i64,
+        limit: i64,
+    ) -> Result<NoteList, NotesServiceError> {
+        const CONDITION: &str = "($1::text IS NULL OR data ? $1 OR data->'envelope'->>'kind' = $1) \
+            AND (expires_at IS NULL OR expires_at > now())";
+        let kind = kind.map(NoteKind::tag);
+        let total: i64 = self
+            .client()
+            .await?
+            .query_one(&format!("SELECT COUNT(*) FROM notes WHERE {CONDITION}"), &[&kind])
+            .await
+            .map_err(NotesServiceError::GetList)?
+            .get(0);
+        let query = format!(
+            "SELECT * FROM notes WHERE {CONDITION} ORDER BY {} OFFSET $2 LIMIT $3",
+            order.as_sql()
+        );
+        let rows = self
+            .client()
+            .await?
+            .query(&query, &[&kind, &offset, &limit])
             .await
-            .map(NoteInfoList::from)
-            .map_err(NotesServiceError::GetList)
+            .map_err(NotesServiceError::GetList)?;
+        // A broken note must not break the whole list
+        let notes = rows
+            .into_iter()
+            .filter_map(|row| {
+                let id: i32 = row.get("id");
+                self.decode(row)
+                    .inspect_err(|err| log::error!("Could not decode note {id}: {err}"))
+                    .ok()
+            })
+            .collect::<Vec<Note>>();
+        Ok(NoteList {
+            items: NoteInfoList::from(notes),
+            total,
+        })
+    }
+
+    /// Returns media notes which are not archived yet, skipping failed notes until their next attempt
+    pub async fn list_unarchived(&self) -> Result<Vec<Note>, NotesServiceError> {
+        self.list(
+            "SELECT * FROM notes WHERE archive_path IS NULL AND NOT (data ?| array['Location', 'Text']) \
+             AND coalesce(data->'envelope'->>'kind' NOT IN ('Location', 'Text'), TRUE) \
+             AND (archive_attempts = 0 OR archive_retry_at <= now()) \
+             AND (expires_at IS NULL OR expires_at > now()) ORDER BY id ASC",
+        )
+        .await
+    }
+
+    pub async fn list_archived(&self) -> Result<Vec<Note>, NotesServiceError> {
+        self.list(
+            "SELECT * FROM notes WHERE archive_path IS NOT NULL \
+             AND (expires_at IS NULL OR expires_at > now()) ORDER BY id ASC",
+        )
+        .await
+    }
+
+    async fn list(&self, query: &str) -> Result<Vec<Note>, NotesServiceError> {
+        let rows = self
+            .client()
+            .await?
+            .query(query, &[])
+            .await
+            .map_err(NotesServiceError::Query)?;
+        self.decode_all(rows)
+    }
+
+    pub async fn mark_used(&self, id: i32) -> Result<(), NotesServiceError> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE notes SET usage_count = usage_count + 1, last_used_at = now() WHERE id = $1",
+                &[&id],
+            )
+            .await
+            .map_err(NotesServiceError::MarkUsed)?;
+        Ok(())
     }
 
     pub async fn remove(&self, id: i32) -> Result<bool, NotesServiceError> {
-        self.client
+        self.client()
+            .await?
             .execute("DELETE FROM notes WHERE id = $1", &[&id])
             .await
             .map(|affected_rows| affected_rows != 0)
@@ -41,38 +212,332 @@ impl NotesService {
     }
 
     pub async fn query(&self, keywords: Keywords) -> Result<Vec<Note>, NotesServiceError> {
+        let keywords = self.keywords_filter(&keywords);
         let rows = self
-            .client
-            .query("SELECT * FROM notes WHERE keywords @> $1", &[&keywords.as_ref()])
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT * FROM notes WHERE {} AND (expires_at IS NULL OR expires_at > now())",
+                    keywords.condition(1)
+                ),
+                &keywords.params(),
+            )
             .await
             .map_err(NotesServiceError::Query)?;
+        self.decode_all(rows)
+    }
+
+    /// Returns text notes which contain placeholders and the given keyword
+    pub async fn query_templates(&self, keyword: &str) -> Result<Vec<Note>, NotesServiceError> {
+        let keywords = self.keywords_filter(&Keywords::from([keyword]));
+        let rows = self
+            .client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT * FROM notes WHERE {} \
+                     AND (data->>'Text' LIKE '%{{%}}%' OR data->'envelope'->>'kind' = 'Text') \
+                     AND (expires_at IS NULL OR expires_at > now())",
+                    keywords.condition(1)
+                ),
+                &keywords.params(),
+            )
+            .await
+            .map_err(NotesServiceError::Query)?;
+        let notes = self.decode_all(rows)?;
+        Ok(notes.into_iter().filter(Note::is_template).collect())
+    }
+
+    /// Returns a random note which contains all of the given keywords
+    pub async fn random(&self, keywords: &Keywords) -> Result<Option<Note>, NotesServiceError> {
+        let keywords = self.keywords_filter(keywords);
+        self.client()
+            .await?
+            .query_opt(
+                &format!(
+                    "SELECT * FROM notes WHERE {} AND (expires_at IS NULL OR expires_at > now()) \
+                     ORDER BY random() LIMIT 1",
+                    keywords.condition(1)
+                ),
+                &keywords.params(),
+            )
+            .await
+            .map_err(NotesServiceError::Query)?
+            .map(|row| self.decode(row))
+            .transpose()
+    }
+
+    pub async fn set_archive_path(&self, id: i32, path: &str) -> Result<(), NotesServiceError> {
+        self.client()
+            .await?
+            .execute("UPDATE notes SET archive_path = $2 WHERE id = $1", &[&id, &path])
+            .await
+            .map_err(NotesServiceError::Update)?;
+        Ok(())
+    }
+
+    /// Records a failed attempt to archive a file of a note
+    ///
+    /// The delay before the next attempt is doubled after every attempt,
+    /// the note is not retried when `retry` is false or after `max_attempts`.
+    pub async fn set_archive_failed(
+        &self,
+        id: i32,
+        retry: bool,
+        delay: Duration,
+        max_attempts: i32,
+    ) -> Result<(), NotesServiceError> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE notes SET archive_attempts = archive_attempts + 1, \
+                 archive_retry_at = CASE WHEN $2 AND archive_attempts + 1 < $4 \
+                 THEN now() + make_interval(secs => $3::float8 * power(2, archive_attempts)) END \
+                 WHERE id = $1",
+                &[&id, &retry, &delay.as_secs_f64(), &max_attempts],
+            )
+            .await
+            .map_err(NotesServiceError::Update)?;
+        Ok(())
+    }
+
+    /// Replaces content and keywords of a note, e.g. when a file was uploaded again
+    pub async fn update(&self, note: &Note) -> Result<bool, NotesServiceError> {
+        let client = self.client().await?;
+        self.write(&client, note, true).await
+    }
+
+    /// Stores a note, `touch` tells whether the note is changed rather than only encoded again
+    async fn write<C>(&self, client: &C, note: &Note, touch: bool) -> Result<bool, NotesServiceError>
+    where
+        C: GenericClient,
+    {
+        let encoded = note
+            .encode(self.cipher.as_deref())
+            .map_err(NotesServiceError::Serialize)?;
+        client
+            .execute(
+                "UPDATE notes SET data = $2, keywords = $3, title = $4, description = $5, \
+                 updated_at = CASE WHEN $6 THEN now() ELSE updated_at END WHERE id = $1",
+                &[
+                    &note.id(),
+                    &encoded.data,
+                    &encoded.keywords,
+                    &encoded.title,
+                    &encoded.description,
+                    &touch,
+                ],
+            )
+            .await
+            .map(|affected_rows| affected_rows != 0)
+            .map_err(NotesServiceError::Update)
+    }
+
+    /// Encrypts all notes using the current key, including expired ones
+    ///
+    /// Notes stored in clear are encrypted as well.
+    /// Returns the number of updated notes.
+    pub async fn reencrypt(&self) -> Result<u64, NotesServiceError> {
+        self.rewrite(|_| true).await
+    }
+
+    /// Rewrites notes stored in an outdated format, including expired ones
+    ///
+    /// Outdated notes are upgraded on read anyway, so this is needed only before the upgrade code is removed.
+    /// Returns the number of updated notes.
+    pub async fn upgrade(&self) -> Result<u64, NotesServiceError> {
+        self.rewrite(Note::is_outdated).await
+    }
+
+    /// Encodes notes again in batches
+    ///
+    /// Every batch is locked until it is written, so concurrent changes made by the bot are not lost.
+    async fn rewrite<F>(&self, filter: F) -> Result<u64, NotesServiceError>
+    where
+        F: Fn(&Note) -> bool,
+    {
+        let mut client = self.client().await?;
+        let mut last_id = 0;
+        let mut count = 0;
+        loop {
+            let transaction = client.transaction().await.map_err(NotesServiceError::Update)?;
+            let rows = transaction
+                .query(
+                    "SELECT * FROM notes WHERE id > $1 ORDER BY id ASC LIMIT $2 FOR UPDATE",
+                    &[&last_id, &REWRITE_BATCH_SIZE],
+                )
+                .await
+                .map_err(NotesServiceError::Query)?;
+            match rows.last() {
+                Some(row) => last_id = row.get("id"),
+                None => break,
+            }
+            for note in self.decode_all(rows)? {
+                if filter(&note) && self.write(&transaction, &note, false).await? {
+                    count += 1;
+                }
+            }
+            transaction.commit().await.map_err(NotesServiceError::Update)?;
+        }
+        Ok(count)
+    }
+
+    /// Removes notes which have expired
+    pub async fn remove_expired(&self) -> Result<u64, NotesServiceError> {
+        self.client()
+            .await?
+            .execute("DELETE FROM notes WHERE expires_at <= now()", &[])
+            .await
+            .map_err(NotesServiceError::RemoveExpired)
+    }
+
+    pub async fn search(&self, query: &NoteQuery, offset: i64, limit: i64) -> Result<NoteSearch, NotesServiceError> {
+        let keywords = self.keywords_filter(&query.keywords);
+        // A MIME type of encrypted notes is checked after decryption
+        let condition = format!(
+            "{} \
+             AND ($1::text IS NULL OR data ? $1 OR data->'envelope'->>'kind' = $1) \
+             AND ($2::text IS NULL OR data ? 'envelope' OR EXISTS (\
+                 SELECT 1 FROM jsonb_each(data) WHERE strpos(lower(value->>'mime_type'), lower($2)) > 0\
+             )) \
+             AND (expires_at IS NULL OR expires_at > now())",
+            keywords.condition(3)
+        );
+        let kind = query.kind.map(NoteKind::tag);
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![&kind, &query.mime_type];
+        params.extend(keywords.params());
+        if query.mime_type.is_some() {
+            let rows = self
+                .client()
+                .await?
+                .query(
+                    &format!("SELECT * FROM notes WHERE {condition} ORDER BY id ASC"),
+                    &params,
+                )
+                .await
+                .map_err(NotesServiceError::Search)?;
+            let items: Vec<Note> = self
+                .decode_all(rows)?
+                .into_iter()
+                .filter(|note| query.matches_mime_type(note.data()))
+                .collect();
+            let total = items.len() as i64;
+            let items = items.into_iter().skip(offset as usize).take(limit as usize).collect();
+            return Ok(NoteSearch { items, total });
+        }
+        let total: i64 = self
+            .client()
+            .await?
+            .query_one(&format!("SELECT COUNT(*) FROM notes WHERE {condition}"), &params)
+            .await
+            .map_err(NotesServiceError::Search)?
+            .get(0);
+        let query = format!(
+            "SELECT * FROM notes WHERE {condition} ORDER BY id ASC OFFSET ${} LIMIT ${}",
+            params.len() + 1,
+            params.len() + 2
+        );
+        params.extend([&offset as &(dyn ToSql + Sync), &limit]);
+        let rows = self
+            .client()
+            .await?
+            .query(&query, &params)
+            .await
+            .map_err(NotesServiceError::Search)?;
+        let items = self.decode_all(rows)?;
+        Ok(NoteSearch { items, total })
+    }
+
+    /// Returns a filter by the `keywords` column
+    fn keywords_filter(&self, keywords: &Keywords) -> KeywordsFilter {
+        let sets = match self.cipher {
+            Some(ref cipher) if cipher.blind_keywords() => cipher.blind_all(keywords.as_ref()),
+            _ => vec![keywords.as_ref().to_vec()],
+        };
+        KeywordsFilter { sets }
+    }
+
+    fn decode(&self, row: Row) -> Result<Note, NotesServiceError> {
+        Note::decode(row, self.cipher.as_deref()).map_err(NotesServiceError::MapNote)
+    }
+
+    fn decode_all(&self, rows: Vec<Row>) -> Result<Vec<Note>, NotesServiceError> {
         rows.into_iter()
-            .map(Note::try_from)
+            .map(|row| Note::decode(row, self.cipher.as_deref()))
             .collect::<Result<Vec<Note>, NoteError>>()
             .map_err(NotesServiceError::MapNote)
     }
 }
 
+/// Matches notes which contain all of the keywords
+///
+/// Blinded keywords are compared using every key of the blind index,
+/// since notes keep keywords blinded with the key they were saved with until keys are rotated.
+struct KeywordsFilter {
+    sets: Vec<Vec<String>>,
+}
+
+impl KeywordsFilter {
+    /// Returns an SQL condition, parameters are numbered starting from `first`
+    fn condition(&self, first: usize) -> String {
+        let items: Vec<String> = (first..first + self.sets.len())
+            .map(|idx| format!("keywords @> ${idx}"))
+            .collect();
+        format!("({})", items.join(" OR "))
+    }
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.sets.iter().map(|set| set as &(dyn ToSql + Sync)).collect()
+    }
+}
+
+pub struct NoteList {
+    pub items: NoteInfoList,
+    pub total: i64,
+}
+
+pub struct NoteSearch {
+    pub items: Vec<Note>,
+    pub total: i64,
+}
+
 #[derive(Debug)]
 pub enum NotesServiceError {
+    Connect(PoolError),
     Create(PgError),
+    Digest(PgError),
+    FindDuplicate(PgError),
+    Get(PgError),
     GetList(PgError),
     MapNote(NoteError),
+    MarkUsed(PgError),
     Query(PgError),
     Remove(PgError),
+    RemoveExpired(PgError),
+    Search(PgError),
     Serialize(NoteDataError),
+    Update(PgError),
 }
 
 impl fmt::Display for NotesServiceError {
     fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
         use self::NotesServiceError::*;
         match self {
+            Connect(err) => write!(out, "get database connection: {err}"),
             Create(err) => write!(out, "create note: {err}"),
+            Digest(err) => write!(out, "get notes for digest: {err}"),
+            FindDuplicate(err) => write!(out, "find duplicate note: {err}"),
+            Get(err) => write!(out, "get note: {err}"),
             GetList(err) => write!(out, "get notes: {err}"),
             MapNote(err) => write!(out, "map note: {err}"),
+            MarkUsed(err) => write!(out, "mark note as used: {err}"),
             Query(err) => write!(out, "query notes: {err}"),
             Remove(err) => write!(out, "remove note: {err}"),
+            RemoveExpired(err) => write!(out, "remove expired notes: {err}"),
+            Search(err) => write!(out, "search notes: {err}"),
             Serialize(err) => write!(out, "can not serialize note: {err}"),
+            Update(err) => write!(out, "update note: {err}"),
         }
     }
 }
@@ -81,12 +546,20 @@ impl Error for NotesServiceError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         use self::NotesServiceError::*;
         Some(match self {
+            Connect(err) => err,
             Create(err) => err,
+            Digest(err) => err,
+            FindDuplicate(err) => err,
+            Get(err) => err,
             GetList(err) => err,
             MapNote(err) => err,
+            MarkUsed(err) => err,
             Query(err) => err,
             Remove(err) => err,
+            RemoveExpired(err) => err,
+            Search(err) => err,
             Serialize(err) => err,
+            Update(err) => err,
         })
     }
 }
@@ -0,0 +1,127 @@
+use std::{error::Error, fmt};
+
+use deadpool_postgres::{Object as PgObject, Pool as PgPool, PoolError};
+use time::OffsetDateTime;
+use tokio_postgres::Error as PgError;
+
+use crate::entities::Reminder;
+
+#[derive(Clone)]
+pub struct RemindersService {
+    pool: PgPool,
+}
+
+impl RemindersService {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn client(&self) -> Result<PgObject, RemindersServiceError> {
+        self.pool.get().await.map_err(RemindersServiceError::Connect)
+    }
+
+    pub async fn create(
+        &self,
+        chat_id: i64,
+        note_id: i32,
+        send_at: OffsetDateTime,
+    ) -> Result<Reminder, RemindersServiceError> {
+        self.client()
+            .await?
+            .query_one(
+                "INSERT INTO reminders (chat_id, note_id, send_at) VALUES ($1, $2, $3) RETURNING *",
+                &[&chat_id, &note_id, &send_at],
+            )
+            .await
+            .map(Reminder::from)
+            .map_err(RemindersServiceError::Create)
+    }
+
+    pub async fn list(&self, chat_id: i64) -> Result<Vec<Reminder>, RemindersServiceError> {
+        self.client()
+            .await?
+            .query(
+                "SELECT * FROM reminders WHERE chat_id = $1 ORDER BY send_at ASC, id ASC",
+                &[&chat_id],
+            )
+            .await
+            .map(|rows| rows.into_iter().map(Reminder::from).collect())
+            .map_err(RemindersServiceError::List)
+    }
+
+    /// Returns reminders which should be sent now
+    pub async fn list_due(&self) -> Result<Vec<Reminder>, RemindersServiceError> {
+        self.client()
+            .await?
+            .query(
+                "SELECT * FROM reminders WHERE send_at <= now() ORDER BY send_at ASC",
+                &[],
+            )
+            .await
+            .map(|rows| rows.into_iter().map(Reminder::from).collect())
+            .map_err(RemindersServiceError::List)
+    }
+
+    /// Postpones a reminder which could not be sent
+    pub async fn retry(&self, id: i32, send_at: OffsetDateTime) -> Result<(), RemindersServiceError> {
+        self.client()
+            .await?
+            .execute(
+                "UPDATE reminders SET send_at = $2, attempts = attempts + 1 WHERE id = $1",
+                &[&id, &send_at],
+            )
+            .await
+            .map_err(RemindersServiceError::Retry)?;
+        Ok(())
+    }
+
+    /// Removes a reminder
+    ///
+    /// When `chat_id` is given, only a reminder created in that chat is removed.
+    pub async fn remove(&self, id: i32, chat_id: Option<i64>) -> Result<bool, RemindersServiceError> {
+        self.client()
+            .await?
+            .execute(
+                "DELETE FROM reminders WHERE id = $1 AND ($2::bigint IS NULL OR chat_id = $2)",
+                &[&id, &chat_id],
+            )
+            .await
+            .map(|affected_rows| affected_rows != 0)
+            .map_err(RemindersServiceError::Remove)
+    }
+}
+
+#[derive(Debug)]
+pub enum RemindersServiceError {
+    Connect(PoolError),
+    Create(PgError),
+    List(PgError),
+    Remove(PgError),
+    Retry(PgError),
+}
+
+impl fmt::Display for RemindersServiceError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::RemindersServiceError::*;
+        match self {
+            Connect(err) => write!(out, "get database connection: {err}"),
+            Create(err) => write!(out, "create reminder: {err}"),
+            List(err) => write!(out, "get reminders: {err}"),
+            Remove(err) => write!(out, "remove reminder: {err}"),
+            Retry(err) => write!(out, "postpone reminder: {err}"),
+        }
+    }
+}
+
+impl Error for RemindersServiceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::RemindersServiceError::*;
+        Some(match self {
+            Connect(err) => err,
+            Create(err) => err,
+            List(err) => err,
+            Remove(err) => err,
+            Retry(err) => err,
+        })
+    }
+}
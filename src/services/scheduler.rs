@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use carapax::{
+    api::Client,
+    types::{ChatPeerId, ReplyParameters, SendMessage},
+};
+use time::{Duration as TimeDuration, OffsetDateTime};
+use tokio::time::interval;
+
+use crate::{
+    entities::{Digest, Reminder},
+    services::{DeliveryService, DigestsService, NotesService, RemindersService, is_permanent_error},
+};
+
+/// A reminder is removed after this number of failed attempts
+const MAX_REMINDER_ATTEMPTS: i32 = 10;
+const REMINDER_RETRY_DELAY: TimeDuration = TimeDuration::minutes(1);
+
+/// Periodically sends notes for due reminders
+pub struct ReminderScheduler {
+    client: Client,
+    delivery_service: DeliveryService,
+    notes_service: NotesService,
+    reminders_service: RemindersService,
+    period: Duration,
+}
+
+impl ReminderScheduler {
+    pub fn new(
+        client: Client,
+        delivery_service: DeliveryService,
+        notes_service: NotesService,
+        reminders_service: RemindersService,
+        period: Duration,
+    ) -> Self {
+        Self {
+            client,
+            delivery_service,
+            notes_service,
+            reminders_service,
+            period,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval = interval(self.period);
+        loop {
+            interval.tick().await;
+            let reminders = match self.reminders_service.list_due().await {
+                Ok(reminders) => reminders,
+                Err(err) => {
+                    log::error!("Could not get due reminders: {err}");
+                    continue;
+                }
+            };
+            for reminder in reminders {
+                let id = reminder.id();
+                let result = match self.send(&reminder).await {
+                    ReminderStatus::Done => self.reminders_service.remove(id, None).await.map(|_| ()),
+                    ReminderStatus::Retry if reminder.attempts() + 1 >= MAX_REMINDER_ATTEMPTS => {
+                        log::error!("Giving up reminder {id} after {MAX_REMINDER_ATTEMPTS} attempts");
+                        self.reminders_service.remove(id, None).await.map(|_| ())
+                    }
+                    ReminderStatus::Retry => {
+                        let send_at = OffsetDateTime::now_utc() + retry_delay(reminder.attempts());
+                        self.reminders_service.retry(id, send_at).await
+                    }
+                };
+                if let Err(err) = result {
+                    log::error!("Could not update reminder {id}: {err}");
+                }
+            }
+        }
+    }
+
+    async fn send(&self, reminder: &Reminder) -> ReminderStatus {
+        let chat_id = ChatPeerId::from(reminder.chat_id());
+        let note = match self.notes_service.get(reminder.note_id()).await {
+            Ok(Some(note)) => note,
+            Ok(None) => return ReminderStatus::Done,
+            Err(err) => {
+                log::error!("Could not get note for reminder {}: {err}", reminder.id());
+                return ReminderStatus::Retry;
+            }
+        };
+        let message = match self.delivery_service.send(chat_id, note.data()).await {
+            Ok(message) => message,
+            Err(err) => {
+                log::error!("Could not send reminder {}: {err}", reminder.id());
+                return if is_permanent_error(&err) {
+                    ReminderStatus::Done
+                } else {
+                    ReminderStatus::Retry
+                };
+            }
+        };
+        // The note is delivered already, so the reminder is not sent again when the reply fails
+        let method = SendMessage::new(chat_id, format!("Reminder: note {}", note.id()))
+            .with_reply_parameters(ReplyParameters::new(message.id));
+        if let Err(err) = self.client.execute(method).await {
+            log::error!("Could not send reminder {}: {err}", reminder.id());
+        }
+        ReminderStatus::Done
+    }
+}
+
+enum ReminderStatus {
+    /// The reminder is sent or can never be sent, so it is removed
+    Done,
+    /// The reminder could not be sent because of a temporary error
+    Retry,
+}
+
+/// Returns a delay before the next attempt, doubled after every failed attempt
+fn retry_delay(attempts: i32) -> TimeDuration {
+    REMINDER_RETRY_DELAY * 2i32.pow(attempts.clamp(0, MAX_REMINDER_ATTEMPTS) as u32)
+}
+
+/// Periodically sends digests of notes
+pub struct DigestScheduler {
+    client: Client,
+    delivery_service: DeliveryService,
+    digests_service: DigestsService,
+    notes_service: NotesService,
+    period: Duration,
+}
+
+impl DigestScheduler {
+    pub fn new(
+        client: Client,
+        delivery_service: DeliveryService,
+        digests_service: DigestsService,
+        notes_service: NotesService,
+        period: Duration,
+    ) -> Self {
+        Self {
+            client,
+            delivery_service,
+            digests_service,
+            notes_service,
+            period,
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval = interval(self.period);
+        loop {
+            interval.tick().await;
+            let digests = match self.digests_service.list_due().await {
+                Ok(digests) => digests,
+                Err(err) => {
+                    log::error!("Could not get due digests: {err}");
+                    continue;
+                }
+            };
+            for digest in digests {
+                let next_at = digest.following_at(OffsetDateTime::now_utc());
+                if let Err(err) = self.digests_service.reschedule(digest.chat_id(), next_at).await {
+                    log::error!("Could not reschedule digest for {}: {err}", digest.chat_id());
+                    continue;
+                }
+                self.send(&digest).await;
+            }
+        }
+    }
+
+    async fn send(&self, digest: &Digest) {
+        let chat_id = ChatPeerId::from(digest.chat_id());
+        let settings = digest.settings();
+        let notes = match self.notes_service.digest(settings.mode, settings.size.into()).await {
+            Ok(notes) => notes,
+            Err(err) => {
+                log::error!("Could not get notes for digest {chat_id}: {err}");
+                return;
+            }
+        };
+        if notes.is_empty() {
+            return;
+        }
+        let text = format!("Your {} digest: {} notes", settings.schedule, notes.len());
+        if let Err(err) = self.client.execute(SendMessage::new(chat_id, text)).await {
+            log::error!("Could not send digest {chat_id}: {err}");
+            return;
+        }
+        for note in notes {
+            let message = match self.delivery_service.send(chat_id, note.data()).await {
+                Ok(message) => message,
+                Err(err) => {
+                    log::error!("Could not send note {} in digest {chat_id}: {err}", note.id());
+                    return;
+                }
+            };
+            let method = SendMessage::new(chat_id, format!("Note {}", note.id()))
+                .with_reply_parameters(ReplyParameters::new(message.id));
+            if let Err(err) = self.client.execute(method).await {
+                log::error!("Could not send note {} in digest {chat_id}: {err}", note.id());
+                return;
+            }
+        }
+    }
+}
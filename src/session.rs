@@ -1,18 +1,412 @@
-use carapax::session::{SessionCollector, backend::redis::RedisBackend};
-use redis::{Client, IntoConnectionInfo, RedisError, aio::ConnectionManager};
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH},
+};
 
-const SESSION_NAMESPACE: &str = "tg-assistant-bot";
-const SESSION_GC_PERIOD: Duration = Duration::from_secs(3_600);
-const SESSION_LIFETIME: Duration = Duration::from_secs(86_400 * 30);
+use carapax::{
+    dialogue::DialogueState,
+    session::{
+        Session, SessionCollector, SessionError,
+        backend::{
+            SessionBackend as Backend,
+            redis::{RedisBackend, RedisBackendError},
+        },
+    },
+};
+use deadpool_postgres::{Object as PgObject, Pool as PgPool, PoolError};
+use redis::{Client, RedisError, aio::ConnectionManager};
+use time::OffsetDateTime;
+use tokio_postgres::Error as PgError;
 
-pub type SessionBackend = RedisBackend<ConnectionManager>;
+use crate::config::{SessionConfig, SessionOptions};
 
-pub async fn create_session_backend<T: IntoConnectionInfo>(session_url: T) -> Result<SessionBackend, RedisError> {
-    let redis_client = Client::open(session_url)?;
-    let redis_manager = ConnectionManager::new(redis_client).await?;
-    let backend = RedisBackend::new(SESSION_NAMESPACE, redis_manager);
-    let mut collector = SessionCollector::new(backend.clone(), SESSION_GC_PERIOD, SESSION_LIFETIME);
+/// Key of the time of the last message in a dialogue, seconds since the Unix epoch
+const DIALOGUE_ACTIVITY_KEY: &str = "dialogue_activity";
+
+pub async fn create_session_backend(
+    config: SessionConfig,
+    options: &SessionOptions,
+    pg_pool: PgPool,
+) -> Result<SessionBackend, SessionBackendError> {
+    let backend = match config {
+        SessionConfig::Memory => SessionBackend::Memory(MemoryBackend::default()),
+        SessionConfig::Postgres => SessionBackend::Postgres(PostgresBackend { pool: pg_pool }),
+        SessionConfig::Redis { url } => {
+            let redis_client = Client::open(url).map_err(SessionBackendError::Redis)?;
+            let redis_manager = ConnectionManager::new(redis_client)
+                .await
+                .map_err(SessionBackendError::Redis)?;
+            SessionBackend::Redis(RedisBackend::new(options.namespace.clone(), redis_manager))
+        }
+    };
+    let mut collector = SessionCollector::new(
+        backend.clone(),
+        Duration::from_secs(options.gc_period),
+        Duration::from_secs(options.lifetime),
+    );
     tokio::spawn(async move { collector.run().await });
     Ok(backend)
 }
+
+/// Records that a dialogue has received a message
+pub async fn touch_dialogue(session: &mut Session<SessionBackend>) -> Result<(), SessionError> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    session.set(DIALOGUE_ACTIVITY_KEY, &now).await
+}
+
+/// Whether a dialogue has not received a message for the given time
+pub async fn is_dialogue_idle(session: &mut Session<SessionBackend>, timeout: Duration) -> Result<bool, SessionError> {
+    let last_activity: Option<i64> = session.get(DIALOGUE_ACTIVITY_KEY).await?;
+    Ok(match last_activity {
+        Some(last_activity) => {
+            let idle = OffsetDateTime::now_utc().unix_timestamp() - last_activity;
+            idle >= 0 && idle as u64 >= timeout.as_secs()
+        }
+        None => false,
+    })
+}
+
+/// Removes the state of a dialogue, so the next message does not continue it
+pub async fn clear_dialogue<S: DialogueState>(session: &mut Session<SessionBackend>) -> Result<(), SessionError> {
+    session.remove(S::session_key()).await?;
+    session.remove(DIALOGUE_ACTIVITY_KEY).await
+}
+
+/// Returns a message telling that a dialogue is cancelled after the given timeout
+pub fn dialogue_timeout_message<S: DialogueState>(timeout: Duration) -> String {
+    let minutes = timeout.as_secs().div_ceil(60);
+    format!(
+        "/{} is cancelled, no message was received for {minutes} min",
+        S::dialogue_name()
+    )
+}
+
+/// Storage of sessions chosen in config
+#[derive(Clone)]
+pub enum SessionBackend {
+    Memory(MemoryBackend),
+    Postgres(PostgresBackend),
+    Redis(RedisBackend<ConnectionManager>),
+}
+
+impl Backend for SessionBackend {
+    type Error = SessionBackendError;
+
+    async fn get_sessions(&mut self) -> Result<Vec<String>, Self::Error> {
+        match self {
+            Self::Memory(backend) => Ok(backend.get_sessions()),
+            Self::Postgres(backend) => backend.get_sessions().await,
+            Self::Redis(backend) => backend.get_sessions().await.map_err(SessionBackendError::RedisBackend),
+        }
+    }
+
+    async fn get_session_age(&mut self, session_id: &str) -> Result<Option<u64>, Self::Error> {
+        match self {
+            Self::Memory(backend) => Ok(backend.get_session_age(session_id)),
+            Self::Postgres(backend) => backend.get_session_age(session_id).await,
+            Self::Redis(backend) => backend
+                .get_session_age(session_id)
+                .await
+                .map_err(SessionBackendError::RedisBackend),
+        }
+    }
+
+    async fn remove_session(&mut self, session_id: &str) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(backend) => {
+                backend.remove_session(session_id);
+                Ok(())
+            }
+            Self::Postgres(backend) => backend.remove_session(session_id).await,
+            Self::Redis(backend) => backend
+                .remove_session(session_id)
+                .await
+                .map_err(SessionBackendError::RedisBackend),
+        }
+    }
+
+    async fn read_value(&mut self, session_id: &str, key: &str) -> Result<Option<Vec<u8>>, Self::Error> {
+        match self {
+            Self::Memory(backend) => Ok(backend.read_value(session_id, key)),
+            Self::Postgres(backend) => backend.read_value(session_id, key).await,
+            Self::Redis(backend) => backend
+                .read_value(session_id, key)
+                .await
+                .map_err(SessionBackendError::RedisBackend),
+        }
+    }
+
+    async fn write_value(&mut self, session_id: &str, key: &str, value: &[u8]) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(backend) => backend.write_value(session_id, key, value),
+            Self::Postgres(backend) => backend.write_value(session_id, key, value).await,
+            Self::Redis(backend) => backend
+                .write_value(session_id, key, value)
+                .await
+                .map_err(SessionBackendError::RedisBackend),
+        }
+    }
+
+    async fn remove_value(&mut self, session_id: &str, key: &str) -> Result<(), Self::Error> {
+        match self {
+            Self::Memory(backend) => {
+                backend.remove_value(session_id, key);
+                Ok(())
+            }
+            Self::Postgres(backend) => backend.remove_value(session_id, key).await,
+            Self::Redis(backend) => backend
+                .remove_value(session_id, key)
+                .await
+                .map_err(SessionBackendError::RedisBackend),
+        }
+    }
+}
+
+/// Keeps sessions in the process memory, so they are lost on restart
+#[derive(Clone, Default)]
+pub struct MemoryBackend {
+    sessions: Arc<Mutex<HashMap<String, MemorySession>>>,
+}
+
+struct MemorySession {
+    /// Seconds since the Unix epoch
+    created_at: u64,
+    values: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryBackend {
+    fn with_sessions<T>(&self, f: impl FnOnce(&mut HashMap<String, MemorySession>) -> T) -> T {
+        // A panic can not leave the map in an inconsistent state, so a poisoned lock is safe to reuse
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut sessions)
+    }
+
+    fn get_sessions(&self) -> Vec<String> {
+        self.with_sessions(|sessions| sessions.keys().cloned().collect())
+    }
+
+    fn get_session_age(&self, session_id: &str) -> Option<u64> {
+        self.with_sessions(|sessions| sessions.get(session_id).map(|session| session.created_at))
+    }
+
+    fn remove_session(&self, session_id: &str) {
+        self.with_sessions(|sessions| sessions.remove(session_id));
+    }
+
+    fn read_value(&self, session_id: &str, key: &str) -> Option<Vec<u8>> {
+        self.with_sessions(|sessions| {
+            sessions
+                .get(session_id)
+                .and_then(|session| session.values.get(key).cloned())
+        })
+    }
+
+    fn write_value(&self, session_id: &str, key: &str, value: &[u8]) -> Result<(), SessionBackendError> {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(SessionBackendError::Clock)?
+            .as_secs();
+        self.with_sessions(|sessions| {
+            sessions
+                .entry(String::from(session_id))
+                .or_insert_with(|| MemorySession {
+                    created_at,
+                    values: HashMap::new(),
+                })
+                .values
+                .insert(String::from(key), value.to_vec());
+        });
+        Ok(())
+    }
+
+    fn remove_value(&self, session_id: &str, key: &str) {
+        self.with_sessions(|sessions| {
+            if let Some(session) = sessions.get_mut(session_id) {
+                session.values.remove(key);
+            }
+        });
+    }
+}
+
+/// Keeps sessions in the `sessions` and `session_values` tables
+#[derive(Clone)]
+pub struct PostgresBackend {
+    pool: PgPool,
+}
+
+impl PostgresBackend {
+    async fn client(&self) -> Result<PgObject, SessionBackendError> {
+        self.pool.get().await.map_err(SessionBackendError::Connect)
+    }
+
+    async fn get_sessions(&self) -> Result<Vec<String>, SessionBackendError> {
+        let rows = self
+            .client()
+            .await?
+            .query("SELECT id FROM sessions", &[])
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        Ok(rows.into_iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn get_session_age(&self, session_id: &str) -> Result<Option<u64>, SessionBackendError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT EXTRACT(EPOCH FROM created_at)::BIGINT FROM sessions WHERE id = $1",
+                &[&session_id],
+            )
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        Ok(row.map(|row| row.get::<_, i64>(0) as u64))
+    }
+
+    async fn remove_session(&self, session_id: &str) -> Result<(), SessionBackendError> {
+        self.client()
+            .await?
+            .execute("DELETE FROM sessions WHERE id = $1", &[&session_id])
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        Ok(())
+    }
+
+    async fn read_value(&self, session_id: &str, key: &str) -> Result<Option<Vec<u8>>, SessionBackendError> {
+        let row = self
+            .client()
+            .await?
+            .query_opt(
+                "SELECT value FROM session_values WHERE session_id = $1 AND key = $2",
+                &[&session_id, &key],
+            )
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn write_value(&self, session_id: &str, key: &str, value: &[u8]) -> Result<(), SessionBackendError> {
+        let mut client = self.client().await?;
+        let transaction = client.transaction().await.map_err(SessionBackendError::Postgres)?;
+        transaction
+            .execute(
+                "INSERT INTO sessions (id) VALUES ($1) ON CONFLICT DO NOTHING",
+                &[&session_id],
+            )
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        transaction
+            .execute(
+                "INSERT INTO session_values (session_id, key, value) VALUES ($1, $2, $3)
+                 ON CONFLICT (session_id, key) DO UPDATE SET value = EXCLUDED.value",
+                &[&session_id, &key, &value],
+            )
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        transaction.commit().await.map_err(SessionBackendError::Postgres)
+    }
+
+    async fn remove_value(&self, session_id: &str, key: &str) -> Result<(), SessionBackendError> {
+        self.client()
+            .await?
+            .execute(
+                "DELETE FROM session_values WHERE session_id = $1 AND key = $2",
+                &[&session_id, &key],
+            )
+            .await
+            .map_err(SessionBackendError::Postgres)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionBackendError {
+    Clock(SystemTimeError),
+    Connect(PoolError),
+    Postgres(PgError),
+    Redis(RedisError),
+    RedisBackend(RedisBackendError),
+}
+
+impl fmt::Display for SessionBackendError {
+    fn fmt(&self, out: &mut fmt::Formatter) -> fmt::Result {
+        use self::SessionBackendError::*;
+        match self {
+            Clock(err) => write!(out, "can not get session timestamp: {err}"),
+            Connect(err) => write!(out, "can not connect to database: {err}"),
+            Postgres(err) => write!(out, "session query failed: {err}"),
+            Redis(err) => write!(out, "Redis connection error: {err}"),
+            RedisBackend(err) => write!(out, "{err}"),
+        }
+    }
+}
+
+impl Error for SessionBackendError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use self::SessionBackendError::*;
+        Some(match self {
+            Clock(err) => err,
+            Connect(err) => err,
+            Postgres(err) => err,
+            Redis(err) => err,
+            RedisBackend(err) => err,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use carapax::session::SessionManager;
+
+    use super::*;
+
+    #[test]
+    fn memory_backend() {
+        let backend = MemoryBackend::default();
+        assert!(backend.get_session_age("session").is_none());
+        backend.write_value("session", "key", b"value").unwrap();
+        assert_eq!(backend.get_sessions(), vec![String::from("session")]);
+        assert!(backend.get_session_age("session").is_some());
+        assert_eq!(backend.read_value("session", "key"), Some(b"value".to_vec()));
+        backend.remove_value("session", "key");
+        assert_eq!(backend.read_value("session", "key"), None);
+        backend.write_value("session", "key", b"value").unwrap();
+        backend.clone().remove_session("session");
+        assert!(backend.get_sessions().is_empty());
+        assert_eq!(backend.read_value("session", "key"), None);
+    }
+
+    #[derive(Default, serde::Deserialize, serde::Serialize)]
+    struct TestState;
+
+    impl DialogueState for TestState {
+        fn dialogue_name() -> &'static str {
+            "test"
+        }
+    }
+
+    #[tokio::test]
+    async fn dialogue_timeout() {
+        let manager = SessionManager::new(SessionBackend::Memory(MemoryBackend::default()));
+        let mut session = manager.get_session("1-1");
+        let timeout = Duration::from_secs(60);
+        assert!(!is_dialogue_idle(&mut session, timeout).await.unwrap());
+
+        session.set(TestState::session_key(), &TestState).await.unwrap();
+        touch_dialogue(&mut session).await.unwrap();
+        assert!(!is_dialogue_idle(&mut session, timeout).await.unwrap());
+        assert!(is_dialogue_idle(&mut session, Duration::ZERO).await.unwrap());
+
+        clear_dialogue::<TestState>(&mut session).await.unwrap();
+        assert!(!is_dialogue_idle(&mut session, Duration::ZERO).await.unwrap());
+        let state: Option<TestState> = session.get(TestState::session_key()).await.unwrap();
+        assert!(state.is_none());
+
+        assert_eq!(
+            dialogue_timeout_message::<TestState>(Duration::from_secs(90)),
+            "/test is cancelled, no message was received for 2 min"
+        );
+    }
+}